@@ -0,0 +1,64 @@
+use std::ops::Deref;
+
+use actix_web::{web, FromRequest, HttpRequest, dev::Payload};
+use actix_web::http::header::AUTHORIZATION;
+use aragog::Record;
+use futures::future::LocalBoxFuture;
+use vertix_model::{Account, AccessToken, Document, Wrap};
+
+use crate::{ApiState, Error};
+
+/// Extractor for the local account that made the request, authenticated by the bearer token in
+/// the `Authorization` header. Requests without a valid token are rejected as unauthorized.
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+    pub account: Document<Account>,
+    pub token: Document<AccessToken>,
+}
+
+impl Deref for Authenticated {
+    type Target = Document<Account>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.account
+    }
+}
+
+impl FromRequest for Authenticated {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let state = req.app_data::<web::Data<ApiState>>().cloned();
+
+        let token = req.headers().get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_owned());
+
+        Box::pin(async move {
+            let state = state
+                .ok_or_else(|| Error::InternalError("ApiState not configured".into()))?;
+
+            let token = token
+                .ok_or_else(|| Error::Unauthorized("Bearer token required".into()))?;
+
+            let db = state.pool.get().await?;
+
+            let token = match AccessToken::find_by_token(&token, &*db).await {
+                Ok(token) => token,
+                Err(e) if e.is_not_found() =>
+                    return Err(Error::Unauthorized("Invalid or expired token".into())),
+                Err(e) => return Err(e.into()),
+            };
+
+            let account: Document<Account> = Account::find(&token.account, &*db).await?.wrap();
+
+            if !account.is_local() {
+                return Err(Error::Unauthorized("Token does not belong to a local account".into()));
+            }
+
+            Ok(Authenticated { account, token })
+        })
+    }
+}
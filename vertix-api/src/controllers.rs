@@ -1,15 +1,21 @@
 use actix_web::web;
 
 mod webfinger;
+mod auth;
 mod account;
 mod note;
 mod interaction;
 mod inbox;
+mod timeline;
+mod media;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.configure(webfinger::config);
+    cfg.configure(auth::config);
     cfg.configure(account::config);
     cfg.configure(note::config);
     cfg.configure(interaction::config);
     cfg.configure(inbox::config);
+    cfg.configure(timeline::config);
+    cfg.configure(media::config);
 }
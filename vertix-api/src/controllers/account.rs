@@ -4,10 +4,16 @@ pub mod fetch;
 pub mod activity;
 pub mod followers;
 pub mod outbox;
+pub mod note;
+pub mod register;
+pub mod profile;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.configure(fetch::config);
     cfg.configure(activity::config);
     cfg.configure(followers::config);
     cfg.configure(outbox::config);
+    cfg.configure(note::config);
+    cfg.configure(register::config);
+    cfg.configure(profile::config);
 }
@@ -1,10 +1,8 @@
 use actix_web::{web, get, Responder};
 use serde::Deserialize;
-use vertix_app_common::helpers::webfinger;
-use vertix_comm::{expect_reply_of, messages::{Action, ActionResponse}};
-use vertix_model::Account;
+use vertix_app_common::helpers::find_or_fetch_account_by_acct;
 
-use crate::{ApiState, Error};
+use crate::ApiState;
 use crate::error::Result;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -23,36 +21,17 @@ async fn lookup_account(
     query: web::Query<LookupQueryParams>,
 ) -> Result<impl Responder> {
     let db = state.pool.get().await?;
-    let LookupQueryParams { username, mut domain } = query.into_inner();
-
-    // Handle own domain
-    if domain.as_deref() == Some(&state.config.domain) {
-        domain = None;
-    }
-
-    match Account::find_by_username(&username, domain.as_deref(), &*db).await {
-        Ok(account) => Ok(web::Json(account)),
-        Err(e) if e.is_not_found() && domain.is_some() => {
-            // Fetch remote account
-            let wf = webfinger(&state.reqwest,
-                "acct",
-                &username,
-                domain.as_deref().unwrap(),
-                true).await?;
-
-            let ch = state.broker.create_channel().await?;
-
-            let url = wf.activitypub()
-                .ok_or(Error::NotFound)?.href.as_deref()
-                .ok_or(Error::NotFound)?.try_into()?;
-
-            let account = expect_reply_of!(
-                Action::FetchAccount(url).remote_call(&ch).await?;
-                ActionResponse::FetchAccount(account) => account
-            )?;
-
-            Ok(web::Json(account))
-        },
-        Err(err) => Err(err.into())
-    }
+    let ch = state.broker.create_channel().await?;
+    let LookupQueryParams { username, domain } = query.into_inner();
+
+    let account = find_or_fetch_account_by_acct(
+        &username,
+        domain.as_deref(),
+        &state.config,
+        &state.reqwest,
+        &*db,
+        &ch
+    ).await?;
+
+    Ok(web::Json(account))
 }
@@ -1,14 +1,15 @@
 use std::sync::Arc;
 
-use aragog::Record;
-use actix_web::{web, get, Responder, http::StatusCode, put};
+use aragog::{Record, EdgeRecord};
+use actix_web::{web, get, Responder, http::StatusCode, put, delete};
 use futures::{stream::FuturesOrdered, TryStreamExt};
 use serde_json::json;
 use vertix_comm::{messages::{Action, ActionResponse}, expect_reply_of};
 use vertix_model::{
     Account,
-    PageLimit,
+    Pagination,
     Follow,
+    Edge,
     activitystreams::{
         ToObject,
         UrlFor,
@@ -18,7 +19,7 @@ use vertix_model::{
     Wrap,
 };
 
-use crate::{ApiState, error::Result, formats::ActivityJson, Error};
+use crate::{ApiState, error::Result, formats::ActivityJson, auth::Authenticated, pagination::PaginationQuery, Error};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(get_account_followers);
@@ -26,6 +27,7 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(get_account_following);
     cfg.service(get_account_following_page);
     cfg.service(initiate_follow);
+    cfg.service(remove_follow);
     cfg.service(list_pending_followers);
     cfg.service(accept_follow);
 }
@@ -44,20 +46,20 @@ pub async fn get_account_followers(
 
     let collection = make_ordered_collection(
         urls.url_for_account_followers(account.key()).await?,
-        urls.url_for_account_followers_page(account.key(), 1).await?,
+        urls.url_for_account_followers_page(account.key(), &Pagination::default()).await?,
         Account::count_followers(&account, &*db).await?
     )?;
 
     Ok(ActivityJson(collection))
 }
 
-#[get("/users/{username}/followers/page/{page}")]
+#[get("/users/{username}/followers/page")]
 pub async fn get_account_followers_page(
-    params: web::Path<(String, u32)>,
+    username: web::Path<String>,
+    query: web::Query<PaginationQuery>,
     state: web::Data<ApiState>
 ) -> Result<impl Responder> {
-    let (username, page) = params.into_inner();
-    let page_limit = PageLimit { page, ..PageLimit::default() };
+    let pagination = query.to_pagination()?;
 
     let db = state.pool.get().await?;
 
@@ -66,8 +68,11 @@ pub async fn get_account_followers_page(
     let account: Arc<_> = Account::find_by_username(&*username, None, &*db).await?.into();
     urls.account_cache.put(account.clone()).await;
 
-    let followers = urls.account_cache.put_many(
-        Account::get_followers(&account, page_limit, &*db).await?).await;
+    let page = Account::get_followers(&account, &pagination, &*db).await?;
+
+    let (next, prev) = (page.next(), page.prev());
+
+    let followers = urls.account_cache.put_many(page.items).await;
 
     // Output should be a collection page of Person
     let items: Vec<_> = FuturesOrdered::from_iter(
@@ -75,9 +80,15 @@ pub async fn get_account_followers_page(
     ).try_collect().await?;
 
     let col_page = make_ordered_collection_page(
-        Some(page_limit),
         urls.url_for_account_followers(account.key()).await?,
-        Some(urls.url_for_account_followers_page(account.key(), page + 1).await?),
+        match next {
+            Some(next) => Some(urls.url_for_account_followers_page(account.key(), &next).await?),
+            None => None,
+        },
+        match prev {
+            Some(prev) => Some(urls.url_for_account_followers_page(account.key(), &prev).await?),
+            None => None,
+        },
         items
     )?;
 
@@ -98,20 +109,20 @@ pub async fn get_account_following(
 
     let collection = make_ordered_collection(
         urls.url_for_account_following(account.key()).await?,
-        urls.url_for_account_following_page(account.key(), 1).await?,
+        urls.url_for_account_following_page(account.key(), &Pagination::default()).await?,
         Account::count_following(&account, &*db).await?
     )?;
 
     Ok(ActivityJson(collection))
 }
 
-#[get("/users/{username}/following/page/{page}")]
+#[get("/users/{username}/following/page")]
 pub async fn get_account_following_page(
-    params: web::Path<(String, u32)>,
+    username: web::Path<String>,
+    query: web::Query<PaginationQuery>,
     state: web::Data<ApiState>
 ) -> Result<impl Responder> {
-    let (username, page) = params.into_inner();
-    let page_limit = PageLimit { page, ..PageLimit::default() };
+    let pagination = query.to_pagination()?;
 
     let db = state.pool.get().await?;
 
@@ -120,8 +131,11 @@ pub async fn get_account_following_page(
     let account: Arc<_> = Account::find_by_username(&*username, None, &*db).await?.into();
     urls.account_cache.put(account.clone()).await;
 
-    let following = urls.account_cache.put_many(
-        Account::get_following(&account, page_limit, &*db).await?).await;
+    let page = Account::get_following(&account, &pagination, &*db).await?;
+
+    let (next, prev) = (page.next(), page.prev());
+
+    let following = urls.account_cache.put_many(page.items).await;
 
     // Output should be a collection page of Person
     let items: Vec<_> = FuturesOrdered::from_iter(
@@ -129,28 +143,37 @@ pub async fn get_account_following_page(
     ).try_collect().await?;
 
     let col_page = make_ordered_collection_page(
-        Some(page_limit),
         urls.url_for_account_following(account.key()).await?,
-        Some(urls.url_for_account_following_page(account.key(), page + 1).await?),
+        match next {
+            Some(next) => Some(urls.url_for_account_following_page(account.key(), &next).await?),
+            None => None,
+        },
+        match prev {
+            Some(prev) => Some(urls.url_for_account_following_page(account.key(), &prev).await?),
+            None => None,
+        },
         items
     )?;
 
     Ok(ActivityJson(col_page))
 }
 
-#[put("/api/v1/accounts/{from}/following/accounts/{to}")]
+#[put("/api/v1/following/accounts/{to}")]
 pub async fn initiate_follow(
-    keys: web::Path<(String, String)>,
+    to: web::Path<String>,
+    auth: Authenticated,
     state: web::Data<ApiState>
 ) -> Result<impl Responder> {
-    let (from, to) = keys.into_inner();
+    if *to == *auth.key() {
+        return Err(Error::BadRequest("Can't follow yourself".into()));
+    }
 
     let ch = state.broker.create_channel().await?;
 
     let (created, follow) = expect_reply_of!(
         Action::InitiateFollow {
-            from_account: from,
-            to_account: to,
+            from_account: auth.key().to_owned(),
+            to_account: to.into_inner(),
             uri: None,
         }.remote_call(&ch).await?;
         ActionResponse::InitiateFollow { created, follow } => (created, follow)
@@ -159,16 +182,33 @@ pub async fn initiate_follow(
     Ok((web::Json(follow), if created { StatusCode::CREATED } else { StatusCode::OK }))
 }
 
-#[get("/api/v1/accounts/{key}/followers/pending")]
+#[delete("/api/v1/following/accounts/{to}")]
+pub async fn remove_follow(
+    to: web::Path<String>,
+    auth: Authenticated,
+    state: web::Data<ApiState>
+) -> Result<impl Responder> {
+    let ch = state.broker.create_channel().await?;
+
+    let removed = expect_reply_of!(
+        Action::RemoveFollow {
+            from_account: auth.key().to_owned(),
+            to_account: to.into_inner(),
+        }.remote_call(&ch).await?;
+        ActionResponse::RemoveFollow { removed } => removed
+    )?;
+
+    Ok(web::Json(removed.ok_or(Error::NotFound)?))
+}
+
+#[get("/api/v1/followers/pending")]
 pub async fn list_pending_followers(
-    key: web::Path<String>,
+    auth: Authenticated,
     state: web::Data<ApiState>
 ) -> Result<impl Responder> {
     let db = state.pool.get().await?;
 
-    let account = Account::find(key.as_str(), &*db).await?.wrap();
-
-    let pending = Follow::find_pending_to(&account, &*db).await?;
+    let pending = Follow::find_pending_to(&auth.account, &*db).await?;
 
     Ok(web::Json(json!({
         "follows": pending
@@ -178,9 +218,18 @@ pub async fn list_pending_followers(
 #[put("/api/v1/follows/{key}/accept")]
 pub async fn accept_follow(
     key: web::Path<String>,
+    auth: Authenticated,
     state: web::Data<ApiState>
 ) -> Result<impl Responder> {
     let ch = state.broker.create_channel().await?;
+    let db = state.pool.get().await?;
+
+    // Only the account being followed gets to accept
+    let follow: Edge<Follow> = EdgeRecord::<Follow>::find(&key, &*db).await?.wrap();
+
+    if *follow.key_to() != **auth.key() {
+        return Err(Error::Forbidden("Follow is not addressed to this account".into()));
+    }
 
     let (modified, follow) = expect_reply_of!(
         Action::SetFollowAccepted { key: key.into_inner(), accepted: true }.remote_call(&ch).await?;
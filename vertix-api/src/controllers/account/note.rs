@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use actix_web::{web, get, Responder, Either, http::StatusCode};
+use activitystreams::ext::Ext;
+use aragog::{DatabaseAccess, Record};
+use futures::{stream::FuturesOrdered, TryStreamExt};
+use vertix_model::{
+    Account,
+    Document,
+    Note,
+    Pagination,
+    Wrap,
+    activitystreams::{
+        ToObject,
+        UrlFor,
+        RepliesExtension,
+        NoteObject,
+        make_tombstone,
+        make_ordered_collection,
+        make_ordered_collection_page,
+    },
+};
+
+use crate::{ApiState, error::Result, formats::ActivityJson, pagination::PaginationQuery, Error};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_account_note);
+    cfg.service(get_account_note_replies);
+    cfg.service(get_account_note_replies_page);
+}
+
+/// Find a local note published by the account with the username. Only notes that anyone can see
+/// are found, apart from deleted ones, which are found so that they can be shown as tombstones.
+async fn find_account_note<D>(username: &str, key: &str, db: &D)
+    -> Result<(Arc<Document<Account>>, Arc<Document<Note>>)>
+where
+    D: DatabaseAccess,
+{
+    let account: Arc<_> = Account::find_by_username(username, None, db).await?.into();
+
+    let note: Arc<Document<Note>> = Arc::new(Note::find(key, db).await?.wrap());
+
+    if note.from.as_deref() != Some(account.key().as_str()) {
+        return Err(Error::NotFound);
+    }
+
+    // Deleted notes have no recipients left, so they'd never be visible
+    if note.is_deleted() {
+        return Ok((account, note));
+    }
+
+    if !Note::can_view(&note, None, db).await? {
+        return Err(Error::NotFound);
+    }
+
+    Ok((account, note))
+}
+
+#[get("/users/{username}/notes/{key}")]
+pub async fn get_account_note(
+    params: web::Path<(String, String)>,
+    state: web::Data<ApiState>
+) -> Result<impl Responder> {
+    let (username, key) = params.into_inner();
+
+    let db = state.pool.get().await?;
+
+    let urls = state.urls(&*db);
+
+    let (account, note) = find_account_note(&username, &key, &*db).await?;
+    urls.account_cache.put(account).await;
+    urls.note_cache.put(note.clone()).await;
+
+    if note.is_deleted() {
+        let tombstone = make_tombstone(
+            urls.url_for_note(note.key()).await?, "Note", note.deleted_at.clone())?;
+
+        return Ok(Either::Left(ActivityJson(tombstone).customize().with_status(StatusCode::GONE)));
+    }
+
+    let mut note_object = note.to_object::<_, Error>(&urls).await?;
+    note_object.object_props.set_context_xsd_any_uri(activitystreams::context())?;
+
+    let note_object: NoteObject = Ext {
+        base: note_object,
+        extension: RepliesExtension {
+            replies: Some(urls.url_for_note_replies(note.key()).await?),
+        },
+    };
+
+    Ok(Either::Right(ActivityJson(note_object)))
+}
+
+#[get("/users/{username}/notes/{key}/replies")]
+pub async fn get_account_note_replies(
+    params: web::Path<(String, String)>,
+    state: web::Data<ApiState>
+) -> Result<impl Responder> {
+    let (username, key) = params.into_inner();
+
+    let db = state.pool.get().await?;
+
+    let urls = state.urls(&*db);
+
+    let (account, note) = find_account_note(&username, &key, &*db).await?;
+    urls.account_cache.put(account).await;
+    urls.note_cache.put(note.clone()).await;
+
+    let collection = make_ordered_collection(
+        urls.url_for_note_replies(note.key()).await?,
+        urls.url_for_note_replies_page(note.key(), &Pagination::default()).await?,
+        Note::count_replies(&note, None, &*db).await?
+    )?;
+
+    Ok(ActivityJson(collection))
+}
+
+#[get("/users/{username}/notes/{key}/replies/page")]
+pub async fn get_account_note_replies_page(
+    params: web::Path<(String, String)>,
+    query: web::Query<PaginationQuery>,
+    state: web::Data<ApiState>
+) -> Result<impl Responder> {
+    let (username, key) = params.into_inner();
+    let pagination = query.to_pagination()?;
+
+    let db = state.pool.get().await?;
+
+    let urls = state.urls(&*db);
+
+    let (account, note) = find_account_note(&username, &key, &*db).await?;
+    urls.account_cache.put(account).await;
+    urls.note_cache.put(note.clone()).await;
+
+    let page = Note::get_replies(&note, None, &pagination, &*db).await?;
+
+    let (next, prev) = (page.next(), page.prev());
+
+    let replies = urls.note_cache.put_many(page.items).await;
+
+    let items: Vec<_> = FuturesOrdered::from_iter(
+        replies.iter().map(|reply| reply.to_object::<_, Error>(&urls))
+    ).try_collect().await?;
+
+    let col_page = make_ordered_collection_page(
+        urls.url_for_note_replies(note.key()).await?,
+        match next {
+            Some(next) => Some(urls.url_for_note_replies_page(note.key(), &next).await?),
+            None => None,
+        },
+        match prev {
+            Some(prev) => Some(urls.url_for_note_replies_page(note.key(), &prev).await?),
+            None => None,
+        },
+        items
+    )?;
+
+    Ok(ActivityJson(col_page))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use vertix_app_common::{Config, helpers::build_reqwest_client, storage::LocalStorage};
+    use vertix_model::{AragogConnectionManager, Recipient, create_connection};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn deleted_notes_are_gone() -> anyhow::Result<()> {
+        let db = create_connection().await?;
+
+        let account = Account::create(Account::new("account41".into()), &db).await?.wrap();
+
+        let note = Note::publish(&account, Note {
+            to: vec![Recipient::Public],
+            ..Note::new("Gone soon".into())
+        }, &db).await?;
+
+        Note::delete_note(note.clone(), &db).await?;
+
+        let config = Config::from_env()?;
+        let state = web::Data::new(ApiState {
+            pool: bb8::Pool::builder().build(AragogConnectionManager).await?,
+            broker: vertix_comm::create_connection().await?,
+            reqwest: build_reqwest_client(&config)?,
+            storage: Box::new(LocalStorage::new(&config.media_dir)),
+            config,
+        });
+
+        let app = test::init_service(App::new().app_data(state).configure(super::config)).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/users/account41/notes/{}", note.key()))
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::GONE);
+
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@ use actix_web::{web, get, Responder};
 use futures::{stream::FuturesOrdered, TryStreamExt};
 use vertix_model::{
     Account,
-    PageLimit,
+    Pagination,
     activitystreams::{
         ToObject,
         UrlFor,
@@ -15,7 +15,7 @@ use vertix_model::{
     },
 };
 
-use crate::{ApiState, error::Result, formats::ActivityJson};
+use crate::{ApiState, error::Result, formats::ActivityJson, pagination::PaginationQuery};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(get_account_outbox);
@@ -36,20 +36,20 @@ pub async fn get_account_outbox(
 
     let collection = make_ordered_collection(
         urls.url_for_account_outbox(account.key()).await?,
-        urls.url_for_account_outbox_page(account.key(), 1).await?,
-        Account::count_published_notes(&account, &*db).await?
+        urls.url_for_account_outbox_page(account.key(), &Pagination::default()).await?,
+        Account::count_published_notes(&account, None, &*db).await?
     )?;
 
     Ok(ActivityJson(collection))
 }
 
-#[get("/users/{username}/outbox/page/{page}")]
+#[get("/users/{username}/outbox/page")]
 pub async fn get_account_outbox_page(
-    params: web::Path<(String, u32)>,
+    username: web::Path<String>,
+    query: web::Query<PaginationQuery>,
     state: web::Data<ApiState>
 ) -> Result<impl Responder> {
-    let (username, page) = params.into_inner();
-    let page_limit = PageLimit { page, ..PageLimit::default() };
+    let pagination = query.to_pagination()?;
 
     let db = state.pool.get().await?;
 
@@ -58,8 +58,11 @@ pub async fn get_account_outbox_page(
     let account: Arc<_> = Account::find_by_username(&*username, None, &*db).await?.into();
     urls.account_cache.put(account.clone()).await;
 
-    let notes = urls.note_cache.put_many(
-        Account::get_published_notes(&account, page_limit, &*db).await?).await;
+    let page = Account::get_published_notes(&account, None, &pagination, &*db).await?;
+
+    let (next, prev) = (page.next(), page.prev());
+
+    let notes = urls.note_cache.put_many(page.items).await;
 
     // Output should be a collection page of Create/Note
     let items: Vec<_> = FuturesOrdered::from_iter(
@@ -71,9 +74,15 @@ pub async fn get_account_outbox_page(
     ).try_collect().await?;
 
     let col_page = make_ordered_collection_page(
-        Some(page_limit),
         urls.url_for_account_outbox(account.key()).await?,
-        Some(urls.url_for_account_outbox_page(account.key(), page + 1).await?),
+        match next {
+            Some(next) => Some(urls.url_for_account_outbox_page(account.key(), &next).await?),
+            None => None,
+        },
+        match prev {
+            Some(prev) => Some(urls.url_for_account_outbox_page(account.key(), &prev).await?),
+            None => None,
+        },
         items
     )?;
 
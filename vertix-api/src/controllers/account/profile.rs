@@ -0,0 +1,116 @@
+use actix_web::{web, patch, Responder};
+use serde::Deserialize;
+use vertix_app_common::content::render_profile;
+use vertix_comm::{expect_reply_of, messages::{Action, ActionResponse}};
+use vertix_model::{Attachment, MediaAttachment, ProfileField};
+
+use crate::{ApiState, Error, auth::Authenticated};
+use crate::error::Result;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(update_profile);
+}
+
+/// Changes to a profile. Anything that's left out stays the same, and empty strings remove the
+/// display name, avatar or header.
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileBody {
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// Plain text.
+    #[serde(default)]
+    pub summary: Option<String>,
+
+    /// Values are plain text.
+    #[serde(default)]
+    pub fields: Option<Vec<ProfileField>>,
+
+    /// Key of an uploaded image.
+    #[serde(default)]
+    pub avatar: Option<String>,
+
+    /// Key of an uploaded image.
+    #[serde(default)]
+    pub header: Option<String>,
+}
+
+/// Find an image that the authenticated account uploaded, to use on its profile. An empty key
+/// gives `None`.
+async fn find_profile_image<D>(
+    auth: &Authenticated,
+    key: &str,
+    db: &D
+) -> Result<Option<Attachment>>
+where
+    D: aragog::DatabaseAccess,
+{
+    if key.is_empty() {
+        return Ok(None);
+    }
+
+    let media_attachment = match MediaAttachment::find_uploaded_by(key, auth.key(), db).await {
+        Ok(media_attachment) => media_attachment,
+        Err(e) if e.is_not_found() =>
+            return Err(Error::BadRequest(format!("Media attachment {key:?} not found").into())),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !media_attachment.media_type.starts_with("image/") {
+        return Err(Error::BadRequest(format!("Media attachment {key:?} is not an image").into()));
+    }
+
+    Ok(Some((&media_attachment).into()))
+}
+
+#[patch("/api/v1/accounts/update_credentials")]
+pub async fn update_profile(
+    state: web::Data<ApiState>,
+    auth: Authenticated,
+    body: web::Json<UpdateProfileBody>
+) -> Result<impl Responder> {
+    let ch = state.broker.create_channel().await?;
+    let db = state.pool.get().await?;
+
+    let UpdateProfileBody { display_name, summary, fields, avatar, header } = body.into_inner();
+
+    let mut profile = auth.profile.clone();
+    let mut source = profile.source.take().unwrap_or_default();
+
+    if let Some(display_name) = display_name {
+        let display_name = display_name.trim();
+        profile.display_name = (!display_name.is_empty()).then(|| display_name.to_owned());
+    }
+
+    if let Some(summary) = summary {
+        source.summary = summary;
+    }
+
+    if let Some(fields) = fields {
+        source.fields = fields;
+    }
+
+    if let Some(ref avatar) = avatar {
+        profile.avatar = find_profile_image(&auth, avatar, &*db).await?;
+    }
+
+    if let Some(ref header) = header {
+        profile.header = find_profile_image(&auth, header, &*db).await?;
+    }
+
+    profile.source = Some(source);
+
+    // Check first, so that we don't wait on a transaction that will fail
+    profile.validate()?;
+
+    render_profile(&mut profile);
+
+    let account = expect_reply_of!(
+        Action::UpdateProfile { account: auth.key().to_owned(), profile }
+            .remote_call(&ch).await?;
+
+        ActionResponse::UpdateProfile { account, .. } => account
+    )?;
+
+    Ok(web::Json(account))
+}
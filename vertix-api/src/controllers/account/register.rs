@@ -0,0 +1,47 @@
+use actix_web::{web, post, Responder, http::StatusCode};
+use serde::Deserialize;
+use vertix_comm::{expect_reply_of, messages::{Action, ActionResponse}};
+use vertix_model::{Account, AccountCredential};
+
+use crate::{ApiState, Error};
+use crate::error::Result;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(register_account);
+}
+
+#[derive(Deserialize)]
+pub struct RegisterBody {
+    pub username: String,
+    pub password: String,
+}
+
+#[post("/api/v1/accounts")]
+pub async fn register_account(
+    state: web::Data<ApiState>,
+    body: web::Json<RegisterBody>
+) -> Result<impl Responder> {
+    let ch = state.broker.create_channel().await?;
+    let db = state.pool.get().await?;
+
+    let RegisterBody { username, password } = body.into_inner();
+
+    // Check as much as we can first, so that we don't wait on a transaction that will fail
+    Account::validate_username(&username)?;
+
+    match Account::find_by_username(&username, None, &*db).await {
+        Ok(_) => return Err(Error::Conflict(format!("Username {username:?} is taken").into())),
+        Err(e) if e.is_not_found() => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    // Hashing is slow, so it's kept off the executor
+    let password_hash = web::block(move || AccountCredential::hash_password(&password)).await??;
+
+    let account = expect_reply_of!(
+        Action::RegisterAccount { username, password_hash }.remote_call(&ch).await?;
+        ActionResponse::RegisterAccount(account) => account
+    )?;
+
+    Ok((web::Json(account), StatusCode::CREATED))
+}
@@ -0,0 +1,87 @@
+use actix_web::{web, get, post, Responder, http::StatusCode};
+use aragog::Record;
+use serde::Deserialize;
+use serde_json::json;
+use vertix_model::{Account, AccountCredential, AccessToken};
+
+use crate::{error::Result, auth::Authenticated, ApiState, Error};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(login);
+    cfg.service(logout);
+    cfg.service(verify_credentials);
+}
+
+#[derive(Deserialize)]
+pub struct LoginBody {
+    pub username: String,
+    pub password: String,
+}
+
+#[post("/api/v1/auth/login")]
+pub async fn login(
+    state: web::Data<ApiState>,
+    body: web::Json<LoginBody>
+) -> Result<impl Responder> {
+    let db = state.pool.get().await?;
+
+    let LoginBody { username, password } = body.into_inner();
+
+    // Same error whether the account or the password is wrong
+    let invalid = || Error::Unauthorized("Invalid username or password".into());
+
+    let account = match Account::find_by_username(&username, None, &*db).await {
+        Ok(account) => Some(account),
+        Err(e) if e.is_not_found() => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let credential = match account {
+        Some(ref account) => match AccountCredential::find_by_account(account.key(), &*db).await {
+            Ok(credential) => Some(credential),
+            Err(e) if e.is_not_found() => None,
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+
+    // Hashing is slow, so it's kept off the executor. It's done even if there's nothing to check
+    // against, so that how long it takes doesn't give away whether the account exists.
+    let verified = web::block(move || match credential {
+        Some(credential) => credential.verify_password(&password),
+        None => AccountCredential::verify_dummy_password(&password),
+    }).await?;
+
+    let account = match account {
+        Some(account) if verified => account,
+        _ => return Err(invalid()),
+    };
+
+    let (token, access_token) = AccessToken::issue(account.key(), &*db).await?;
+
+    Ok((web::Json(json!({
+        "access_token": token,
+        "token_type": "Bearer",
+        "expires_at": access_token.expires_at,
+        "account": account,
+    })), StatusCode::CREATED))
+}
+
+#[post("/api/v1/auth/logout")]
+pub async fn logout(
+    state: web::Data<ApiState>,
+    auth: Authenticated
+) -> Result<impl Responder> {
+    let db = state.pool.get().await?;
+
+    auth.token.delete(&*db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[get("/api/v1/accounts/verify_credentials")]
+pub async fn verify_credentials(
+    auth: Authenticated
+) -> Result<impl Responder> {
+    Ok(web::Json(auth.account))
+}
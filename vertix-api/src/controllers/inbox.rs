@@ -1,10 +1,16 @@
-use actix_web::{web, routes, Responder, http::StatusCode};
+use actix_web::{web, routes, Responder, HttpRequest, http::StatusCode};
 use activitystreams::activity::ActivityBox;
+use aragog::DatabaseAccess;
+use lapin::Channel;
 use serde::Deserialize;
-use vertix_comm::messages::ReceiveActivity;
-use vertix_comm::SendMessage;
+use serde_json::Value;
+use url::Url;
+use vertix_app_common::{helpers, http_signatures::{self, Signature}};
+use vertix_comm::messages::{ReceiveActivity, Action, ActionResponse};
+use vertix_comm::{SendMessage, expect_reply_of};
+use vertix_model::{Account, Document};
 
-use crate::{ApiState, error::Result};
+use crate::{ApiState, Error, error::Result};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(post_inbox);
@@ -21,14 +27,109 @@ pub struct PathData {
 #[post("/inbox")]
 pub async fn post_inbox(
     state: web::Data<ApiState>,
+    req: HttpRequest,
     _path_data: web::Path<PathData>,
-    content: web::Json<ActivityBox>,
+    body: web::Bytes,
 ) -> Result<impl Responder> {
+    let content: Value = serde_json::from_slice(&body)
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+
+    let db = state.db().await?;
     let ch = state.broker.create_channel().await?;
 
-    ReceiveActivity {
-        activity: content.into_inner(),
-    }.send(&ch).await?;
+    let signer = verify_signer(&state, &req, &body, &*db, &ch).await?;
+
+    // The signer can only send activities on their own behalf
+    let actor_uri = activity_actor(&content)
+        .ok_or(Error::BadRequest("activity actor is missing".into()))?;
+
+    if signer.remote.as_ref().map(|r| &r.uri) != Some(&actor_uri) {
+        return Err(Error::Unauthorized(
+            format!("activity actor {actor_uri} does not match the signer").into()));
+    }
+
+    let activity: ActivityBox = serde_json::from_value(content)
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+
+    ReceiveActivity { activity }.send(&ch).await?;
 
     Ok(("", StatusCode::CREATED))
 }
+
+/// Get the actor of an activity, which can be either a URI or an embedded object.
+fn activity_actor(activity: &Value) -> Option<Url> {
+    match activity.get("actor")? {
+        Value::String(uri) => uri.parse().ok(),
+        Value::Object(object) => object.get("id")?.as_str()?.parse().ok(),
+        _ => None
+    }
+}
+
+/// Verify the HTTP signature and digest of a request, and return the account that signed it.
+///
+/// The signer's key is looked up from their actor document, which is found by stripping the
+/// fragment from the `keyId`. If we don't have the key yet, or the signature doesn't verify with
+/// the one we have, the actor is fetched again in case the key has been changed.
+async fn verify_signer<D>(
+    state: &ApiState,
+    req: &HttpRequest,
+    body: &[u8],
+    db: &D,
+    ch: &Channel,
+) -> Result<Document<Account>>
+where
+    D: DatabaseAccess,
+{
+    let get_header = |name: &str| req.headers().get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_owned());
+
+    let digest = get_header("digest")
+        .ok_or(Error::Unauthorized("digest header is missing".into()))?;
+
+    http_signatures::verify_digest(&digest, body)?;
+
+    let signature: Signature = get_header("signature")
+        .ok_or(Error::Unauthorized("signature header is missing".into()))?
+        .parse()?;
+
+    let mut actor_uri: Url = signature.key_id.parse()
+        .map_err(|_| Error::Unauthorized("keyId is not a URL".into()))?;
+    actor_uri.set_fragment(None);
+
+    if state.config.is_own_url(&actor_uri) {
+        return Err(Error::Unauthorized("local accounts can't deliver to the inbox".into()));
+    }
+
+    let verify = |account: &Document<Account>| -> http_signatures::Result<()> {
+        let public_key = account.remote.as_ref()
+            .and_then(|remote| remote.public_key.as_ref())
+            .filter(|public_key| public_key.id.as_str() == signature.key_id)
+            .ok_or_else(|| http_signatures::Error::Key("signer's key is not known".into()))?;
+
+        let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+        signature.verify(req.method().as_str(), path_and_query, true, get_header,
+            &public_key.pem)
+    };
+
+    let account = helpers::find_or_fetch_account_by_uri(&actor_uri, &state.config, db, ch)
+        .await?;
+
+    match verify(&account) {
+        Ok(()) => Ok(account),
+        Err(http_signatures::Error::BadSignature) |
+        Err(http_signatures::Error::Key(_)) => {
+            log::debug!("Refreshing key for {actor_uri} to verify signature");
+
+            let account = expect_reply_of!(
+                Action::FetchAccount(actor_uri).remote_call(ch).await?;
+                ActionResponse::FetchAccount(account) => account
+            )?;
+
+            verify(&account)?;
+            Ok(account)
+        },
+        Err(err) => Err(err.into()),
+    }
+}
@@ -2,12 +2,12 @@ use std::time::Duration;
 
 use actix_web::{web, get, Responder};
 use actix_web_lab::sse;
-use vertix_model::Recipient;
+use vertix_model::{Note, Recipient};
 use vertix_comm::messages::Interaction;
 use serde::Deserialize;
 use futures::stream::TryStreamExt;
 
-use crate::{error::Result, ApiState};
+use crate::{error::Result, auth::Authenticated, ApiState, Error};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(get_interactions_stream);
@@ -43,16 +43,48 @@ impl GetInteractionsStreamQuery {
     }
 }
 
+/// Check whether the viewer is allowed to see the interaction, and if so, strip what they
+/// shouldn't see from it.
+async fn filter_interaction(
+    state: &ApiState,
+    viewer: Option<&Authenticated>,
+    mut item: Interaction,
+) -> Result<Option<Interaction>> {
+    if let Some(note) = item.note() {
+        let db = state.pool.get().await?;
+
+        if !Note::can_view(note, viewer.map(|auth| &auth.account), &*db).await? {
+            return Ok(None);
+        }
+    }
+
+    if let Some(note) = item.note_mut() {
+        note.hide_private_recipients(viewer.map(|auth| auth.key().as_str()));
+    }
+
+    Ok(Some(item))
+}
+
 #[get("/api/v1/interactions.stream")]
 pub async fn get_interactions_stream(
     state: web::Data<ApiState>,
+    auth: Option<Authenticated>,
     query: web::Query<GetInteractionsStreamQuery>,
 ) -> Result<impl Responder> {
     let ch = state.broker.create_channel().await?;
 
     let stream = Interaction::listen(&ch, &query.from(), &query.to()).await?;
 
-    Ok(sse::Sse::from_stream(stream.and_then(|item| async {
-        Ok(sse::Data::new_json(item)?.into())
-    })).with_keep_alive(Duration::from_secs(20)))
+    let stream = stream
+        .map_err(Error::from)
+        .try_filter_map(move |item| {
+            let state = state.clone();
+            let auth = auth.clone();
+            async move { filter_interaction(&state, auth.as_ref(), item).await }
+        })
+        .and_then(|item| async {
+            Ok::<_, Error>(sse::Data::new_json(item)?.into())
+        });
+
+    Ok(sse::Sse::from_stream(stream).with_keep_alive(Duration::from_secs(20)))
 }
@@ -0,0 +1,159 @@
+use actix_web::{web, get, HttpRequest, HttpResponse, Responder, http::{header, StatusCode}};
+use aragog::Record;
+use serde::Deserialize;
+use vertix_comm::{expect_reply_of, messages::{Action, ActionResponse}};
+use vertix_app_common::media;
+use vertix_model::{MediaAttachment, Document, Wrap};
+
+use crate::{error::Result, auth::Authenticated, ApiState, Error};
+
+/// The largest file that can be uploaded, in bytes.
+pub const MAX_UPLOAD_SIZE: usize = 16 * 1024 * 1024;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/api/v1/media")
+            .app_data(web::PayloadConfig::new(MAX_UPLOAD_SIZE))
+            .route(web::post().to(upload_media))
+    );
+    cfg.service(get_media_attachment);
+    cfg.service(get_media_file);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadMediaQuery {
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Computed by the client, as we don't decode images.
+    #[serde(default)]
+    pub blurhash: Option<String>,
+}
+
+/// Upload a file to attach to notes. The body is the file itself, and its `Content-Type` is taken
+/// as the media type.
+pub async fn upload_media(
+    state: web::Data<ApiState>,
+    auth: Authenticated,
+    req: HttpRequest,
+    query: web::Query<UploadMediaQuery>,
+    body: web::Bytes
+) -> Result<impl Responder> {
+    let media_type = req.headers().get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| value.contains('/'))
+        .ok_or_else(|| Error::BadRequest("Content-Type must be set to the file's type".into()))?;
+
+    if body.is_empty() {
+        return Err(Error::BadRequest("File is empty".into()));
+    }
+
+    if !media::is_allowed(&media_type, &body) {
+        return Err(Error::BadRequest(
+            "Only images, video and audio can be uploaded, and the file must match its \
+                Content-Type".into()));
+    }
+
+    let ch = state.broker.create_channel().await?;
+
+    let UploadMediaQuery { description, blurhash } = query.into_inner();
+
+    // Store the file first, so that there's never a record without a file
+    let key = MediaAttachment::generate_key();
+
+    state.storage.put(&key, &body).await?;
+
+    let result: Result<Document<MediaAttachment>> = async {
+        Ok(expect_reply_of!(
+            Action::CreateMediaAttachment {
+                key: key.clone(),
+                media_attachment: MediaAttachment {
+                    account: Some(auth.key().into()),
+                    size: Some(body.len() as u64),
+                    description,
+                    blurhash,
+                    ..MediaAttachment::new(media_type)
+                },
+            }.remote_call(&ch).await?;
+
+            ActionResponse::CreateMediaAttachment(media_attachment) => media_attachment
+        )?)
+    }.await;
+
+    match result {
+        Ok(media_attachment) => Ok((web::Json(media_attachment), StatusCode::CREATED)),
+        Err(e) => {
+            if let Err(e) = state.storage.delete(&key).await {
+                log::warn!("Failed to remove file of media attachment {key}: {e}");
+            }
+
+            Err(e)
+        },
+    }
+}
+
+/// Get a media attachment that the viewer can see. Ones that they can't see are reported as not
+/// found.
+#[get("/api/v1/media/{key}")]
+pub async fn get_media_attachment(
+    state: web::Data<ApiState>,
+    auth: Option<Authenticated>,
+    path: web::Path<String>
+) -> Result<impl Responder> {
+    let db = state.pool.get().await?;
+
+    let media_attachment: Document<MediaAttachment> =
+        MediaAttachment::find(&path, &*db).await?.wrap();
+
+    let viewer = auth.as_ref().map(|auth| &auth.account);
+
+    if !MediaAttachment::can_view(&media_attachment, viewer, &*db).await? {
+        return Err(Error::NotFound);
+    }
+
+    Ok(web::Json(media_attachment))
+}
+
+/// Serve the file of a local media attachment. Remote ones aren't kept here, so they're not found.
+///
+/// This isn't checked against who can see the attachment, as other servers fetch the files of
+/// notes that they're sent without signing the request. Keys are random, so only those who have
+/// been given the url can find the file.
+///
+/// Browsers are told not to guess the type, and anything that isn't safe to show inline is served
+/// as a download, so that a file can never run as a page on our origin.
+#[get("/media/{key}")]
+pub async fn get_media_file(
+    state: web::Data<ApiState>,
+    path: web::Path<String>
+) -> Result<impl Responder> {
+    let db = state.pool.get().await?;
+
+    let media_attachment: Document<MediaAttachment> =
+        MediaAttachment::find(&path, &*db).await?.wrap();
+
+    if media_attachment.is_remote() {
+        return Err(Error::NotFound);
+    }
+
+    let data = match state.storage.get(media_attachment.key()).await {
+        Ok(data) => data,
+        Err(e) if e.is_not_found() => return Err(Error::NotFound),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut response = HttpResponse::Ok();
+
+    response
+        .content_type(media_attachment.media_type.as_str())
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((header::CACHE_CONTROL, "public, max-age=31536000, immutable"));
+
+    if !media::is_inline_safe(&media_attachment.media_type) {
+        response.insert_header((header::CONTENT_DISPOSITION, "attachment"));
+    }
+
+    Ok(response.body(data))
+}
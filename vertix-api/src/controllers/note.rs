@@ -1,50 +1,197 @@
-use actix_web::{web, get, post, Responder};
+use actix_web::{web, get, post, put, delete, Responder, http::StatusCode};
 use aragog::Record;
-use vertix_model::{Note, Account};
+use vertix_model::{
+    Account,
+    Note,
+    NoteSource,
+    ContentType,
+    MediaAttachment,
+    Document,
+    Recipient,
+    Visibility,
+    Wrap,
+};
 use vertix_comm::messages::{Action, ActionResponse};
 use vertix_comm::expect_reply_of;
+use vertix_app_common::helpers::{parse_mentions, parse_hashtags, find_or_fetch_account_by_acct};
+use vertix_app_common::content::render_note_content;
 use serde::Deserialize;
+use serde_json::json;
 
-use crate::{error::Result, ApiState};
+use crate::{error::Result, auth::Authenticated, ApiState, Error};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(get_note);
     cfg.service(publish_note);
+    cfg.service(edit_note);
+    cfg.service(delete_note);
+    cfg.service(get_note_context);
+    cfg.service(get_note_likes);
+    cfg.service(like_note);
+    cfg.service(unlike_note);
+    cfg.service(share_note);
+    cfg.service(unshare_note);
+}
+
+/// Find a note that the viewer is allowed to see. Notes that they can't see are reported as not
+/// found, so that their existence isn't revealed.
+async fn find_visible_note<D>(
+    viewer: Option<&Authenticated>,
+    key: &str,
+    db: &D
+) -> Result<Document<Note>>
+where
+    D: aragog::DatabaseAccess,
+{
+    let note: Document<Note> = Note::find(key, db).await?.wrap();
+
+    if !Note::can_view(&note, viewer.map(|auth| &auth.account), db).await? {
+        return Err(Error::NotFound);
+    }
+
+    Ok(note)
 }
 
 #[get("/api/v1/notes/{key}")]
 pub async fn get_note(
     state: web::Data<ApiState>,
+    auth: Option<Authenticated>,
     path: web::Path<String>
 ) -> Result<impl Responder> {
     let db = state.pool.get().await?;
 
-    let note = Note::find(&*path, &*db).await?;
+    let mut note = find_visible_note(auth.as_ref(), &path, &*db).await?;
+
+    if note.is_deleted() {
+        return Err(Error::Gone);
+    }
+
+    note.hide_private_recipients(auth.as_ref().map(|auth| auth.key().as_str()));
 
     Ok(web::Json(note))
 }
 
-// FIXME auth
+/// A new note. Only what the author chooses is taken from the client, and the rest of the note is
+/// worked out from it.
 #[derive(Debug, Deserialize)]
-pub struct PublishNoteQuery {
-    pub from_username: String,
+pub struct PublishNoteBody {
+    /// The source of the note, which the content is rendered from.
+    pub content: String,
+
+    #[serde(default)]
+    pub media_type: ContentType,
+
+    /// Defaults to public. `to` and `cc` are filled in to match.
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+
+    /// Keys of accounts to address the note to, besides those mentioned.
+    #[serde(default)]
+    pub to: Vec<String>,
+
+    /// Keys of accounts to copy the note to.
+    #[serde(default)]
+    pub cc: Vec<String>,
+
+    /// Key of the note this is a reply to.
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+
+    /// Keys of uploaded media attachments to attach, in order.
+    #[serde(default)]
+    pub media_attachments: Vec<String>,
+}
+
+/// Address accounts by key, checking that they exist.
+async fn find_recipients<D>(keys: &[String], db: &D) -> Result<Vec<Recipient>>
+where
+    D: aragog::DatabaseAccess,
+{
+    let mut recipients = vec![];
+
+    for key in keys {
+        match Account::find(key, db).await.map_err(vertix_model::Error::from) {
+            Ok(_) => recipients.push(Recipient::Account(key.clone())),
+            Err(e) if e.is_not_found() =>
+                return Err(Error::BadRequest(format!("Account {key:?} not found").into())),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(recipients)
 }
 
 #[post("/api/v1/notes")]
 pub async fn publish_note(
     state: web::Data<ApiState>,
-    query: web::Query<PublishNoteQuery>,
-    body: web::Json<Note>
+    auth: Authenticated,
+    body: web::Json<PublishNoteBody>
 ) -> Result<impl Responder> {
     let ch = state.broker.create_channel().await?;
     let db = state.pool.get().await?;
 
-    let account = Account::find_by_username(&query.from_username, None, &*db).await?;
+    let PublishNoteBody {
+        content,
+        media_type,
+        visibility,
+        to,
+        cc,
+        in_reply_to,
+        media_attachments,
+    } = body.into_inner();
+
+    // Check first, so that we don't wait on a transaction that will fail
+    if let Some(ref parent_key) = in_reply_to {
+        find_visible_note(Some(&auth), parent_key, &*db).await?;
+    }
+
+    let mut note = Note {
+        to: find_recipients(&to, &*db).await?,
+        cc: find_recipients(&cc, &*db).await?,
+        in_reply_to,
+        ..Note::new(String::new())
+    };
+
+    note.apply_visibility(visibility.unwrap_or(Visibility::Public), auth.key());
+
+    let source = NoteSource::new(content, media_type);
+
+    note.hashtags = parse_hashtags(&source.content);
+
+    // Mentions that can't be resolved to an account are left as plain text
+    for mention in parse_mentions(&source.content) {
+        match find_or_fetch_account_by_acct(
+            &mention.username,
+            mention.domain.as_deref(),
+            &state.config,
+            &state.reqwest,
+            &*db,
+            &ch
+        ).await {
+            Ok(account) => note.add_mention(&account, mention.name),
+            Err(e) => log::debug!("Couldn't resolve mention {}: {e}", mention.name),
+        }
+    }
+
+    note.source = Some(source);
+    render_note_content(&mut note, &state.urls(&*db)).await?;
+
+    // Only the uploader's own attachments can be used
+    for key in &media_attachments {
+        let media_attachment = MediaAttachment::find_uploaded_by(key, auth.key(), &*db).await
+            .map_err(|e| match e {
+                e if e.is_not_found() =>
+                    Error::BadRequest(format!("Media attachment {key:?} not found").into()),
+                e => e.into(),
+            })?;
+
+        note.attachments.push((&media_attachment).into());
+    }
 
     let note = expect_reply_of!(
         Action::PublishNote(Note {
-            from: Some(account.key().into()),
-            ..body.into_inner()
+            from: Some(auth.key().into()),
+            ..note
         }).remote_call(&ch).await?;
 
         ActionResponse::PublishNote(note) => note
@@ -52,3 +199,240 @@ pub async fn publish_note(
 
     Ok(web::Json(note))
 }
+
+/// Find a note that the authenticated account is allowed to change, i.e. one that it authored.
+async fn find_own_note<D>(
+    auth: &Authenticated,
+    key: &str,
+    db: &D
+) -> Result<Document<Note>>
+where
+    D: aragog::DatabaseAccess,
+{
+    let note: Document<Note> = Note::find(key, db).await?.wrap();
+
+    if note.is_deleted() {
+        return Err(Error::Gone);
+    }
+
+    if note.from.as_deref() != Some(auth.key().as_str()) {
+        return Err(Error::Forbidden("Note belongs to another account".into()));
+    }
+
+    Ok(note)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditNoteBody {
+    /// The new source of the note.
+    pub content: String,
+
+    /// Defaults to the media type of the current source.
+    #[serde(default)]
+    pub media_type: Option<ContentType>,
+}
+
+#[put("/api/v1/notes/{key}")]
+pub async fn edit_note(
+    state: web::Data<ApiState>,
+    auth: Authenticated,
+    path: web::Path<String>,
+    body: web::Json<EditNoteBody>
+) -> Result<impl Responder> {
+    let ch = state.broker.create_channel().await?;
+    let db = state.pool.get().await?;
+
+    // Make sure it exists first, so that we don't wait on a transaction that will fail
+    let mut note = find_own_note(&auth, &path, &*db).await?;
+
+    let EditNoteBody { content, media_type } = body.into_inner();
+
+    let media_type = media_type
+        .or_else(|| note.source.as_ref().map(|source| source.media_type))
+        .unwrap_or_default();
+
+    // Only the mentions that were resolved when the note was published are linked, but the
+    // hashtags are taken from the new source
+    let hashtags = parse_hashtags(&content);
+
+    note.source = Some(NoteSource::new(content, media_type));
+    render_note_content(&mut note, &state.urls(&*db)).await?;
+
+    let note = expect_reply_of!(
+        Action::EditNote {
+            key: path.into_inner(),
+            content: note.content.clone(),
+            source: note.source.clone(),
+            hashtags,
+            updated_at: None,
+        }.remote_call(&ch).await?;
+        ActionResponse::EditNote { note, .. } => note
+    )?;
+
+    Ok(web::Json(note))
+}
+
+#[delete("/api/v1/notes/{key}")]
+pub async fn delete_note(
+    state: web::Data<ApiState>,
+    auth: Authenticated,
+    path: web::Path<String>
+) -> Result<impl Responder> {
+    let ch = state.broker.create_channel().await?;
+    let db = state.pool.get().await?;
+
+    // Make sure it exists first, so that we don't wait on a transaction that will fail
+    let own_note = find_own_note(&auth, &path, &*db).await?;
+
+    let note = expect_reply_of!(
+        Action::DeleteNote(path.into_inner()).remote_call(&ch).await?;
+        ActionResponse::DeleteNote { note, .. } => note
+    )?;
+
+    // Attachments that aren't used elsewhere were removed with the note, so their files can go too
+    for attachment in &own_note.attachments {
+        if let (None, Some(key)) = (&attachment.remote_url, &attachment.media_attachment) {
+            match MediaAttachment::find(key, &*db).await.map_err(vertix_model::Error::from) {
+                Ok(_) => (),
+                Err(e) if e.is_not_found() => {
+                    if let Err(e) = state.storage.delete(key).await {
+                        log::warn!("Failed to remove file of media attachment {key}: {e}");
+                    }
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    Ok(web::Json(note))
+}
+
+#[get("/api/v1/notes/{key}/context")]
+pub async fn get_note_context(
+    state: web::Data<ApiState>,
+    auth: Option<Authenticated>,
+    path: web::Path<String>
+) -> Result<impl Responder> {
+    let db = state.pool.get().await?;
+
+    let note = find_visible_note(auth.as_ref(), &path, &*db).await?;
+
+    let viewer = auth.as_ref().map(|auth| &auth.account);
+
+    let (mut ancestors, mut descendants) = futures::try_join!(
+        Note::get_ancestors(&note, viewer, &*db),
+        Note::get_descendants(&note, viewer, &*db),
+    )?;
+
+    for note in ancestors.iter_mut().chain(descendants.iter_mut()) {
+        note.hide_private_recipients(viewer.map(|account| account.key().as_str()));
+    }
+
+    Ok(web::Json(json!({
+        "ancestors": ancestors,
+        "descendants": descendants,
+    })))
+}
+
+#[get("/api/v1/notes/{key}/likes")]
+pub async fn get_note_likes(
+    state: web::Data<ApiState>,
+    auth: Option<Authenticated>,
+    path: web::Path<String>
+) -> Result<impl Responder> {
+    let db = state.pool.get().await?;
+
+    let note = find_visible_note(auth.as_ref(), &path, &*db).await?;
+
+    Ok(web::Json(json!({
+        "count": Note::count_likes(&note, &*db).await?
+    })))
+}
+
+#[put("/api/v1/liked/notes/{note}")]
+pub async fn like_note(
+    note: web::Path<String>,
+    auth: Authenticated,
+    state: web::Data<ApiState>
+) -> Result<impl Responder> {
+    let account = auth.key().to_owned();
+    let note = note.into_inner();
+
+    let ch = state.broker.create_channel().await?;
+    let db = state.pool.get().await?;
+
+    // Check first, so that we don't wait on a transaction that will fail
+    find_visible_note(Some(&auth), &note, &*db).await?;
+
+    let (created, like) = expect_reply_of!(
+        Action::LikeNote { account, note, uri: None }.remote_call(&ch).await?;
+        ActionResponse::LikeNote { created, like } => (created, like)
+    )?;
+
+    Ok((web::Json(like), if created { StatusCode::CREATED } else { StatusCode::OK }))
+}
+
+#[delete("/api/v1/liked/notes/{note}")]
+pub async fn unlike_note(
+    note: web::Path<String>,
+    auth: Authenticated,
+    state: web::Data<ApiState>
+) -> Result<impl Responder> {
+    let account = auth.key().to_owned();
+    let note = note.into_inner();
+
+    let ch = state.broker.create_channel().await?;
+
+    let removed = expect_reply_of!(
+        Action::UnlikeNote { account, note }.remote_call(&ch).await?;
+        ActionResponse::UnlikeNote { removed } => removed
+    )?;
+
+    Ok(web::Json(removed.ok_or(Error::NotFound)?))
+}
+
+#[put("/api/v1/shared/notes/{note}")]
+pub async fn share_note(
+    note: web::Path<String>,
+    auth: Authenticated,
+    state: web::Data<ApiState>
+) -> Result<impl Responder> {
+    let account = auth.key().to_owned();
+    let note = note.into_inner();
+
+    let ch = state.broker.create_channel().await?;
+    let db = state.pool.get().await?;
+
+    // Check first, so that we don't wait on a transaction that will fail
+    let found = find_visible_note(Some(&auth), &note, &*db).await?;
+
+    if !found.can_share() {
+        return Err(Error::Forbidden("Only public and unlisted notes can be shared".into()));
+    }
+
+    let (created, share) = expect_reply_of!(
+        Action::ShareNote { account, note, uri: None }.remote_call(&ch).await?;
+        ActionResponse::ShareNote { created, share } => (created, share)
+    )?;
+
+    Ok((web::Json(share), if created { StatusCode::CREATED } else { StatusCode::OK }))
+}
+
+#[delete("/api/v1/shared/notes/{note}")]
+pub async fn unshare_note(
+    note: web::Path<String>,
+    auth: Authenticated,
+    state: web::Data<ApiState>
+) -> Result<impl Responder> {
+    let account = auth.key().to_owned();
+    let note = note.into_inner();
+
+    let ch = state.broker.create_channel().await?;
+
+    let removed = expect_reply_of!(
+        Action::UnshareNote { account, note }.remote_call(&ch).await?;
+        ActionResponse::UnshareNote { removed } => removed
+    )?;
+
+    Ok(web::Json(removed.ok_or(Error::NotFound)?))
+}
@@ -0,0 +1,103 @@
+use actix_web::{web, get, Responder};
+use serde::Deserialize;
+use vertix_model::{Account, Hashtag, Note, Page, TimelineEntry, TimelineOptions};
+
+use crate::{error::Result, auth::Authenticated, pagination::{PaginationQuery, PageJson}, ApiState};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_home_timeline);
+    cfg.service(get_local_timeline);
+    cfg.service(get_federated_timeline);
+    cfg.service(get_tag_timeline);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimelineQuery {
+    #[serde(default)]
+    pub exclude_replies: bool,
+
+    #[serde(default)]
+    pub exclude_shares: bool,
+}
+
+impl TimelineQuery {
+    pub fn options(&self) -> TimelineOptions {
+        TimelineOptions {
+            exclude_replies: self.exclude_replies,
+            exclude_shares: self.exclude_shares,
+        }
+    }
+}
+
+/// Strip the recipients that only the author should see from every note on the page.
+fn hide_private_recipients(page: Page<TimelineEntry>, viewer_key: Option<&str>)
+    -> Page<TimelineEntry>
+{
+    page.map(|mut entry| {
+        entry.note.hide_private_recipients(viewer_key);
+        entry
+    })
+}
+
+#[get("/api/v1/timelines/home")]
+pub async fn get_home_timeline(
+    state: web::Data<ApiState>,
+    auth: Authenticated,
+    query: web::Query<TimelineQuery>,
+    page_query: web::Query<PaginationQuery>
+) -> Result<impl Responder> {
+    let pagination = page_query.to_pagination()?;
+
+    let db = state.pool.get().await?;
+
+    let page = Account::get_home_timeline(
+        &auth.account, query.options(), &pagination, &*db).await?;
+
+    Ok(PageJson(hide_private_recipients(page, Some(auth.key().as_str()))))
+}
+
+#[get("/api/v1/timelines/local")]
+pub async fn get_local_timeline(
+    state: web::Data<ApiState>,
+    query: web::Query<TimelineQuery>,
+    page_query: web::Query<PaginationQuery>
+) -> Result<impl Responder> {
+    let pagination = page_query.to_pagination()?;
+
+    let db = state.pool.get().await?;
+
+    let page = Note::get_local_timeline(query.options(), &pagination, &*db).await?;
+
+    Ok(PageJson(hide_private_recipients(page, None)))
+}
+
+#[get("/api/v1/timelines/public")]
+pub async fn get_federated_timeline(
+    state: web::Data<ApiState>,
+    query: web::Query<TimelineQuery>,
+    page_query: web::Query<PaginationQuery>
+) -> Result<impl Responder> {
+    let pagination = page_query.to_pagination()?;
+
+    let db = state.pool.get().await?;
+
+    let page = Note::get_federated_timeline(query.options(), &pagination, &*db).await?;
+
+    Ok(PageJson(hide_private_recipients(page, None)))
+}
+
+#[get("/api/v1/timelines/tag/{name}")]
+pub async fn get_tag_timeline(
+    state: web::Data<ApiState>,
+    name: web::Path<String>,
+    query: web::Query<TimelineQuery>,
+    page_query: web::Query<PaginationQuery>
+) -> Result<impl Responder> {
+    let pagination = page_query.to_pagination()?;
+
+    let db = state.pool.get().await?;
+
+    let page = Hashtag::get_timeline(&name, query.options(), &pagination, &*db).await?;
+
+    Ok(PageJson(hide_private_recipients(page, None)))
+}
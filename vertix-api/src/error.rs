@@ -37,6 +37,9 @@ pub enum Error {
     #[error("url parse error: {0}")]
     UrlParse(#[from] url::ParseError),
 
+    #[error("blocking task error: {0}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+
     #[error("webfinger fetch error: {0}")]
     WebfingerFetch(reqwest::Error),
 
@@ -46,6 +49,18 @@ pub enum Error {
     #[error("not found")]
     NotFound,
 
+    #[error("gone")]
+    Gone,
+
+    #[error("bad request: {0}")]
+    BadRequest(Cow<'static, str>),
+
+    #[error("unauthorized: {0}")]
+    Unauthorized(Cow<'static, str>),
+
+    #[error("forbidden: {0}")]
+    Forbidden(Cow<'static, str>),
+
     #[error("conflict: {0}")]
     Conflict(Cow<'static, str>)
 }
@@ -60,6 +75,10 @@ impl ResponseError for Error {
             Error::WebfingerFetch(_) => StatusCode::NOT_FOUND,
 
             Error::NotFound => StatusCode::NOT_FOUND,
+            Error::Gone => StatusCode::GONE,
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
             Error::Conflict(_) => StatusCode::CONFLICT,
 
             Error::Pool(_) |
@@ -68,6 +87,7 @@ impl ResponseError for Error {
             Error::Io(_) |
             Error::Json(_) |
             Error::UrlParse(_) |
+            Error::Blocking(_) |
             Error::InternalError(_) =>
                 StatusCode::INTERNAL_SERVER_ERROR,
         }
@@ -88,6 +108,7 @@ macro_rules! impl_via {
 
 impl_via!(vertix_model::Error | aragog::Error, Arc<aragog::Error>);
 impl_via!(vertix_comm::Error | lapin::Error);
+impl_via!(vertix_app_common::Error | vertix_app_common::http_signatures::Error);
 
 impl From<Infallible> for Error {
     fn from(_err: Infallible) -> Self {
@@ -112,7 +133,9 @@ impl From<vertix_app_common::Error> for Error {
             Model(m) => m.into(),
             Comm(c) => c.into(),
             UrlParse(u) => u.into(),
+            Io(e) => e.into(),
             WebfingerFetch(r) => Error::WebfingerFetch(r),
+            HttpSignature(e) => Error::Unauthorized(e.to_string().into()),
             InternalError(e) => Error::InternalError(e),
         }
     }
@@ -10,10 +10,13 @@ use log::{info, warn};
 use vertix_app_common::helpers::build_reqwest_client;
 use vertix_model::AragogConnectionManager;
 use vertix_app_common::{Urls, Config};
+use vertix_app_common::storage::{Storage, LocalStorage};
 
 mod formats;
 mod controllers;
 mod error;
+mod auth;
+mod pagination;
 
 pub use error::Error;
 
@@ -22,6 +25,7 @@ pub struct ApiState {
     pool: bb8::Pool<AragogConnectionManager>,
     broker: lapin::Connection,
     reqwest: reqwest::Client,
+    storage: Box<dyn Storage>,
 }
 
 impl ApiState {
@@ -101,7 +105,9 @@ async fn main() -> Result<()> {
 
     let reqwest = build_reqwest_client(&config)?;
 
-    let state = web::Data::new(ApiState { config, pool, broker, reqwest });
+    let storage = Box::new(LocalStorage::new(&config.media_dir));
+
+    let state = web::Data::new(ApiState { config, pool, broker, reqwest, storage });
 
     serve(state).await?;
 
@@ -0,0 +1,102 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web::body::BoxBody;
+use actix_web::http::header::LINK;
+use serde::{Deserialize, Serialize};
+use vertix_model::{Cursor, Page, Pagination};
+
+use crate::{ApiState, Error};
+
+/// Query parameters for endpoints that return a [Page].
+#[derive(Debug, Default, Deserialize)]
+pub struct PaginationQuery {
+    #[serde(default)]
+    pub max_id: Option<String>,
+
+    #[serde(default)]
+    pub since_id: Option<String>,
+
+    #[serde(default)]
+    pub min_id: Option<String>,
+
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl PaginationQuery {
+    pub fn to_pagination(&self) -> Result<Pagination, Error> {
+        let parse = |cursor: &Option<String>| cursor.as_deref()
+            .map(|s| s.parse::<Cursor>())
+            .transpose();
+
+        Ok(Pagination {
+            max_id: parse(&self.max_id)?,
+            since_id: parse(&self.since_id)?,
+            min_id: parse(&self.min_id)?,
+            ..Pagination::with_limit(self.limit.unwrap_or(Pagination::default().limit))
+        })
+    }
+}
+
+/// Responds with the items of a [Page] as JSON, with a `Link` header pointing to the pages
+/// before and after it.
+#[derive(Debug, Clone)]
+pub struct PageJson<T>(pub Page<T>);
+
+impl<T> PageJson<T> {
+    fn link_header(&self, req: &HttpRequest) -> Option<String> {
+        let state = req.app_data::<web::Data<ApiState>>()?;
+
+        let url = state.config.base_url.join(req.path().trim_start_matches('/')).ok()?;
+
+        // Keep the other parameters (e.g. filters) in the links
+        let other_params: Vec<(String, String)> = url::form_urlencoded::parse(
+                req.query_string().as_bytes())
+            .filter(|(name, _)| !matches!(&**name, "max_id" | "since_id" | "min_id" | "limit"))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+
+        let link = |pagination: Pagination, rel: &str| {
+            let mut url = url.clone();
+
+            let mut query = url::form_urlencoded::Serializer::new(String::new());
+            query.extend_pairs(&other_params);
+            let mut query = query.finish();
+
+            let page_query = pagination.to_query();
+            if !query.is_empty() && !page_query.is_empty() {
+                query.push('&');
+            }
+            query.push_str(&page_query);
+
+            url.set_query(if query.is_empty() { None } else { Some(&query) });
+            format!("<{url}>; rel=\"{rel}\"")
+        };
+
+        let links: Vec<String> = [
+            self.0.next().map(|next| link(next, "next")),
+            self.0.prev().map(|prev| link(prev, "prev")),
+        ].into_iter().flatten().collect();
+
+        if links.is_empty() {
+            None
+        } else {
+            Some(links.join(", "))
+        }
+    }
+}
+
+impl<T> Responder for PageJson<T>
+    where T: Serialize,
+{
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let mut res = HttpResponse::Ok();
+
+        if let Some(link_header) = self.link_header(req) {
+            res.insert_header((LINK, link_header));
+        }
+
+        res.json(self.0.items)
+    }
+}
@@ -0,0 +1,209 @@
+//! Rendering of local note content to HTML.
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+use regex::{Captures, Regex};
+use url::Url;
+use vertix_model::{
+    ContentType,
+    Hashtag,
+    Note,
+    Profile,
+    ProfileField,
+    activitystreams::UrlFor,
+    sanitize_html,
+};
+
+use crate::helpers::parse_hashtags;
+
+/// Where mentions and hashtags in content should link to.
+#[derive(Debug, Clone, Default)]
+pub struct Links {
+    /// By lowercase mention name, e.g. `@user@domain`.
+    pub mentions: HashMap<String, Url>,
+
+    /// By normalized hashtag name.
+    pub hashtags: HashMap<String, Url>,
+}
+
+/// Render `note.source` to HTML in `note.content`, linking urls, the note's mentions and its
+/// hashtags. Does nothing if the note has no source.
+pub async fn render_note_content<U>(note: &mut Note, urls: &U) -> Result<(), U::Error>
+where
+    U: UrlFor,
+{
+    let source = match note.source {
+        Some(ref source) => source,
+        None => return Ok(()),
+    };
+
+    let mut links = Links::default();
+
+    for mention in &note.mentions {
+        links.mentions.insert(mention.name.to_lowercase(),
+            urls.url_for_account(&mention.account).await?);
+    }
+
+    for name in Hashtag::normalize_names(&parse_hashtags(&source.content)) {
+        let url = urls.url_for_hashtag(&name)?;
+        links.hashtags.insert(name, url);
+    }
+
+    note.content = match source.media_type {
+        ContentType::PlainText => render_plain_text(&source.content, &links),
+        ContentType::Markdown => render_markdown(&source.content, &links),
+    };
+
+    Ok(())
+}
+
+/// Render `profile.source` to HTML in `profile.summary` and `profile.fields`. Urls are linked,
+/// but mentions and hashtags aren't. Does nothing if the profile has no source.
+pub fn render_profile(profile: &mut Profile) {
+    let source = match profile.source {
+        Some(ref source) => source,
+        None => return,
+    };
+
+    let links = Links::default();
+
+    profile.summary = Some(render_plain_text(&source.summary, &links))
+        .filter(|summary| !summary.is_empty());
+
+    profile.fields = source.fields.iter()
+        .map(|field| ProfileField {
+            name: field.name.clone(),
+            value: sanitize_html(&linkify(&field.value, &links)),
+        })
+        .collect();
+}
+
+/// Render plain text to HTML. Blank lines separate paragraphs, and other line breaks are kept.
+pub fn render_plain_text(text: &str, links: &Links) -> String {
+    lazy_static! {
+        static ref PARAGRAPH_BREAK: Regex = Regex::new(r"\n[ \t]*\n\s*").unwrap();
+    }
+
+    let text = text.replace("\r\n", "\n");
+
+    let html: String = PARAGRAPH_BREAK.split(text.trim())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| {
+            let lines: Vec<String> = paragraph.lines().map(|line| linkify(line, links)).collect();
+            format!("<p>{}</p>", lines.join("<br>"))
+        })
+        .collect();
+
+    sanitize_html(&html)
+}
+
+/// Render Markdown to HTML. Raw HTML in the Markdown is sanitized along with the rest.
+pub fn render_markdown(text: &str, links: &Links) -> String {
+    let mut events: Vec<Event> = vec![];
+
+    // Text is split up around anything that might have been markup, so join it back together
+    // before looking for links in it
+    for event in Parser::new_ext(text, Options::ENABLE_STRIKETHROUGH) {
+        match (events.last_mut(), event) {
+            (Some(Event::Text(prev)), Event::Text(text)) =>
+                *prev = [&**prev, &*text].concat().into(),
+            (_, event) => events.push(event),
+        }
+    }
+
+    // Don't link inside of links, images or code
+    let mut no_links_depth = 0;
+
+    let events = events.into_iter().map(|event| match event {
+        Event::Start(Tag::Link(..) | Tag::Image(..) | Tag::CodeBlock(..)) => {
+            no_links_depth += 1;
+            event
+        },
+        Event::End(Tag::Link(..) | Tag::Image(..) | Tag::CodeBlock(..)) => {
+            no_links_depth -= 1;
+            event
+        },
+        Event::Text(text) if no_links_depth == 0 => Event::Html(linkify(&text, links).into()),
+        event => event,
+    });
+
+    let mut html = String::new();
+    html::push_html(&mut html, events);
+
+    sanitize_html(&html)
+}
+
+/// Escape text for HTML, turning urls, known mentions and known hashtags into links.
+fn linkify(text: &str, links: &Links) -> String {
+    lazy_static! {
+        static ref REGEX: Regex = Regex::new(concat!(
+            r#"(?P<url>https?://[^\s<>"]*[^\s<>".,;:!?')])"#,
+            r"|(?:^|[^\w@/.])(?P<mention>@\w+(?:@[\w-]+(?:\.[\w-]+)*(?::\d+)?)?)",
+            r"|(?:^|[^\w&/#])(?P<hashtag>#\w+)",
+        )).unwrap();
+    }
+
+    let mut html = String::new();
+    let mut last = 0;
+
+    for captures in REGEX.captures_iter(text) {
+        let (matched, href, class) = match link_of(&captures, links) {
+            Some(link) => link,
+            None => continue,
+        };
+
+        html.push_str(&escape(&text[last..matched.start()]));
+
+        match class {
+            Some(class) => html.push_str(&format!(r#"<a href="{}" class="{class}">{}</a>"#,
+                escape(href.as_str()), escape(matched.as_str()))),
+            None => html.push_str(&format!(r#"<a href="{}">{}</a>"#,
+                escape(href.as_str()), escape(matched.as_str()))),
+        }
+
+        last = matched.end();
+    }
+
+    html.push_str(&escape(&text[last..]));
+    html
+}
+
+/// Find what a match of the link regex links to, if anything.
+fn link_of<'t>(captures: &Captures<'t>, links: &Links)
+    -> Option<(regex::Match<'t>, Url, Option<&'static str>)>
+{
+    if let Some(url) = captures.name("url") {
+        return Some((url, url.as_str().parse().ok()?, None));
+    }
+
+    if let Some(mention) = captures.name("mention") {
+        let href = links.mentions.get(&mention.as_str().to_lowercase())?;
+        return Some((mention, href.clone(), Some("mention")));
+    }
+
+    if let Some(hashtag) = captures.name("hashtag") {
+        let href = links.hashtags.get(&Hashtag::normalize_name(hashtag.as_str())?)?;
+        return Some((hashtag, href.clone(), Some("hashtag")));
+    }
+
+    None
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
@@ -16,10 +16,26 @@ pub enum Error {
     #[error("webfinger fetch error: {0}")]
     WebfingerFetch(reqwest::Error),
 
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("http signature error: {0}")]
+    HttpSignature(#[from] crate::http_signatures::Error),
+
     #[error("{0}")]
     InternalError(Cow<'static, str>),
 }
 
+impl Error {
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::Model(e) => e.is_not_found(),
+            Error::Io(e) => e.kind() == std::io::ErrorKind::NotFound,
+            _ => false
+        }
+    }
+}
+
 impl From<Infallible> for Error {
     fn from(_: Infallible) -> Self {
         unreachable!()
@@ -1,14 +1,15 @@
 use std::{fs::File, io::Read};
 
 use actix_webfinger::Webfinger;
-use aragog::DatabaseAccess;
+use aragog::{DatabaseAccess, Record};
 use lapin::Channel;
 use reqwest::header;
 use url::Url;
 use regex::Regex;
 use lazy_static::lazy_static;
 use urlencoding::encode;
-use vertix_model::{Account, Document};
+use serde_json::json;
+use vertix_model::{Account, Document, Note, Wrap};
 use vertix_comm::{
     messages::{Action, ActionResponse},
     expect_reply_of
@@ -45,7 +46,21 @@ where
 
     // Foreign account url
     match Account::find_by_uri(&uri, &*db).await {
-        Ok(account) => Ok(account),
+        Ok(account) => {
+            // Use what we have for now, and refresh it in the background if it's old
+            if account.is_stale(config.account_max_age) {
+                let requested = async {
+                    Action::RecordRefreshAttempts(vec![account.key().into()]).send(&ch).await?;
+                    Action::FetchAccount(uri.to_owned()).send(&ch).await
+                }.await;
+
+                if let Err(e) = requested {
+                    log::warn!("Failed to request refresh of stale account {uri}: {e}");
+                }
+            }
+
+            Ok(account)
+        },
         Err(e) if e.is_not_found() => {
             let account = expect_reply_of!(
                 Action::FetchAccount(uri.to_owned()).remote_call(&ch).await?;
@@ -58,6 +73,152 @@ where
     }
 }
 
+/// Find a note by its uri, which may be either one of our own note urls or the uri of a remote
+/// note that we have.
+pub async fn find_note_by_uri<D>(
+    uri: &Url,
+    config: &Config,
+    db: &D,
+) -> Result<Document<Note>>
+where
+    D: DatabaseAccess,
+{
+    if config.is_own_url(uri) {
+        lazy_static! {
+            static ref REGEX: Regex = Regex::new("/users/[^/]+/notes/([^/]+)$").unwrap();
+        }
+
+        let key = REGEX.captures(uri.path())
+            .ok_or(Error::InternalError("This is not a note URL".into()))?
+            .get(1).unwrap()
+            .as_str();
+
+        Ok(Note::find(key, &*db).await?.wrap())
+    } else {
+        Ok(Note::find_by_uri(uri, &*db).await?)
+    }
+}
+
+/// Find a note by its uri, fetching it from the remote server if we don't have it yet.
+pub async fn find_or_fetch_note_by_uri<D>(
+    uri: &Url,
+    config: &Config,
+    db: &D,
+    ch: &Channel,
+) -> Result<Document<Note>>
+where
+    D: DatabaseAccess,
+{
+    match find_note_by_uri(uri, config, db).await {
+        Ok(note) => Ok(note),
+        Err(e) if e.is_not_found() && !config.is_own_url(uri) => {
+            let note = expect_reply_of!(
+                Action::FetchNote(uri.to_owned()).remote_call(&ch).await?;
+                ActionResponse::FetchNote(note) => note
+            )?;
+
+            Ok(note)
+        },
+        Err(e) => Err(e)
+    }
+}
+
+/// Find an account by username and domain (`None` for local accounts). Remote accounts that we
+/// don't have yet are looked up with webfinger and fetched.
+pub async fn find_or_fetch_account_by_acct<D>(
+    username: &str,
+    domain: Option<&str>,
+    config: &Config,
+    client: &reqwest::Client,
+    db: &D,
+    ch: &Channel,
+) -> Result<Document<Account>>
+where
+    D: DatabaseAccess,
+{
+    // Handle own domain
+    let domain = domain.filter(|domain| !domain.eq_ignore_ascii_case(&config.domain));
+
+    match Account::find_by_username(username, domain, db).await {
+        Ok(account) => Ok(account),
+        Err(e) if e.is_not_found() && domain.is_some() => {
+            let domain = domain.unwrap();
+
+            let wf = webfinger(client, "acct", username, domain, true).await?;
+
+            let not_found = || vertix_model::Error::NotFound {
+                model: "Account".into(),
+                params: json!({"username": username, "domain": domain}),
+            };
+
+            let url = wf.activitypub()
+                .and_then(|link| link.href.as_deref())
+                .ok_or_else(not_found)?
+                .parse()?;
+
+            let account = expect_reply_of!(
+                Action::FetchAccount(url).remote_call(&ch).await?;
+                ActionResponse::FetchAccount(account) => account
+            )?;
+
+            Ok(account)
+        },
+        Err(e) => Err(e.into())
+    }
+}
+
+/// A mention of an account in note content, like `@user` or `@user@domain`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedMention {
+    /// The mention as it's written.
+    pub name: String,
+    pub username: String,
+    pub domain: Option<String>,
+}
+
+/// Find the mentions in note content, in order of first appearance and without duplicates.
+pub fn parse_mentions(content: &str) -> Vec<ParsedMention> {
+    lazy_static! {
+        static ref REGEX: Regex = Regex::new(
+            r"(?:^|[^\w@/.])(@(\w+)(?:@([\w-]+(?:\.[\w-]+)*(?::\d+)?))?)"
+        ).unwrap();
+    }
+
+    let mut mentions: Vec<ParsedMention> = vec![];
+
+    for captures in REGEX.captures_iter(content) {
+        let mention = ParsedMention {
+            name: captures[1].to_owned(),
+            username: captures[2].to_owned(),
+            domain: captures.get(3).map(|domain| domain.as_str().to_owned()),
+        };
+
+        let duplicate = mentions.iter().any(|other| {
+            other.username.eq_ignore_ascii_case(&mention.username) &&
+                other.domain.as_deref().map(str::to_lowercase) ==
+                    mention.domain.as_deref().map(str::to_lowercase)
+        });
+
+        if !duplicate {
+            mentions.push(mention);
+        }
+    }
+
+    mentions
+}
+
+/// Find the hashtags in note content, without the `#`, in order of first appearance. They still
+/// have to be normalized with [Hashtag::normalize_names](vertix_model::Hashtag::normalize_names).
+pub fn parse_hashtags(content: &str) -> Vec<String> {
+    lazy_static! {
+        static ref REGEX: Regex = Regex::new(r"(?:^|[^\w&/#])#(\w+)").unwrap();
+    }
+
+    REGEX.captures_iter(content)
+        .map(|captures| captures[1].to_owned())
+        .collect()
+}
+
 pub async fn webfinger(
     client: &reqwest::Client,
     scheme: &str,
@@ -0,0 +1,268 @@
+//! HTTP Signatures (draft-cavage-http-signatures) and `Digest` headers, as used by ActivityPub
+//! servers to authenticate server-to-server requests.
+
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
+
+use rsa::{PaddingScheme, PublicKey, RsaPrivateKey, RsaPublicKey};
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// The headers covered by the signature on outgoing requests.
+pub const SIGNED_HEADERS: &[&str] = &["(request-target)", "host", "date", "digest"];
+
+/// Requests with a `Date` further than this from the current time are rejected.
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(12 * 60 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("missing header: {0}")]
+    MissingHeader(Cow<'static, str>),
+
+    #[error("malformed signature header: {0}")]
+    MalformedSignature(Cow<'static, str>),
+
+    #[error("unsupported algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+
+    #[error("malformed digest header")]
+    MalformedDigest,
+
+    #[error("digest does not match body")]
+    DigestMismatch,
+
+    #[error("date is invalid or outside of the allowed window")]
+    InvalidDate,
+
+    #[error("signature verification failed")]
+    BadSignature,
+
+    #[error("key error: {0}")]
+    Key(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The headers that must be added to a request for it to be signed.
+#[derive(Debug, Clone)]
+pub struct SignedHeaders {
+    pub date: String,
+    pub digest: String,
+    pub signature: String,
+}
+
+/// A parsed `Signature` header.
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub key_id: String,
+    pub algorithm: Option<String>,
+    pub headers: Vec<String>,
+    pub signature: Vec<u8>,
+}
+
+/// Make the value of a `Digest` header for the given body.
+pub fn make_digest(body: &[u8]) -> String {
+    format!("SHA-256={}", base64::encode(Sha256::digest(body)))
+}
+
+/// Check the value of a `Digest` header against the body. Only SHA-256 is supported.
+pub fn verify_digest(digest: &str, body: &[u8]) -> Result<()> {
+    let expected = digest.split(',')
+        .filter_map(|part| part.trim().split_once('='))
+        .find(|(algorithm, _)| algorithm.eq_ignore_ascii_case("SHA-256"))
+        .map(|(_, value)| value)
+        .ok_or(Error::MalformedDigest)?;
+
+    let expected = base64::decode(expected).map_err(|_| Error::MalformedDigest)?;
+
+    if expected.as_slice() == Sha256::digest(body).as_slice() {
+        Ok(())
+    } else {
+        Err(Error::DigestMismatch)
+    }
+}
+
+/// The value the `Host` header will have for a request to the given url.
+fn host_for(url: &Url) -> Result<String> {
+    let host = url.host_str()
+        .ok_or_else(|| Error::MissingHeader("host".into()))?;
+
+    Ok(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_owned(),
+    })
+}
+
+/// Build the string that gets signed, from the headers covered by the signature.
+///
+/// `get_header` must return the value of a (lowercase) header name, if present.
+fn signing_string(
+    method: &str,
+    path_and_query: &str,
+    headers: &[impl AsRef<str>],
+    get_header: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let lines = headers.iter().map(|name| {
+        let name = name.as_ref().to_lowercase();
+
+        let value = if name == "(request-target)" {
+            format!("{} {path_and_query}", method.to_lowercase())
+        } else {
+            get_header(&name).ok_or_else(|| Error::MissingHeader(name.clone().into()))?
+        };
+
+        Ok(format!("{name}: {value}"))
+    }).collect::<Result<Vec<_>>>()?;
+
+    Ok(lines.join("\n"))
+}
+
+/// Sign a POST of `body` to `url`, returning the headers that should be sent with the request.
+pub fn sign_post(
+    url: &Url,
+    body: &[u8],
+    key_id: &str,
+    private_key_pem: &str,
+) -> Result<SignedHeaders> {
+    let private_key = RsaPrivateKey::from_pkcs8_pem(private_key_pem)
+        .map_err(|e| Error::Key(e.to_string()))?;
+
+    let date = httpdate::fmt_http_date(SystemTime::now());
+    let digest = make_digest(body);
+    let host = host_for(url)?;
+
+    let mut path_and_query = url.path().to_owned();
+    if let Some(query) = url.query() {
+        path_and_query.push('?');
+        path_and_query.push_str(query);
+    }
+
+    let to_sign = signing_string("post", &path_and_query, SIGNED_HEADERS, |name| match name {
+        "host" => Some(host.clone()),
+        "date" => Some(date.clone()),
+        "digest" => Some(digest.clone()),
+        _ => None
+    })?;
+
+    let signature = private_key.sign(
+        PaddingScheme::new_pkcs1v15_sign::<Sha256>(),
+        &Sha256::digest(to_sign.as_bytes()),
+    ).map_err(|e| Error::Key(e.to_string()))?;
+
+    let signature = format!(
+        r#"keyId="{key_id}",algorithm="rsa-sha256",headers="{headers}",signature="{signature}""#,
+        headers = SIGNED_HEADERS.join(" "),
+        signature = base64::encode(signature),
+    );
+
+    Ok(SignedHeaders { date, digest, signature })
+}
+
+/// Build a signed POST of `body` to `url`. Other headers can be added to the returned builder, but
+/// they won't be covered by the signature.
+pub fn signed_post(
+    client: &reqwest::Client,
+    url: Url,
+    body: Vec<u8>,
+    key_id: &str,
+    private_key_pem: &str,
+) -> Result<reqwest::RequestBuilder> {
+    let SignedHeaders { date, digest, signature } =
+        sign_post(&url, &body, key_id, private_key_pem)?;
+
+    Ok(client.post(url)
+        .header(reqwest::header::DATE, date)
+        .header("Digest", digest)
+        .header("Signature", signature)
+        .body(body))
+}
+
+impl std::str::FromStr for Signature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Signature> {
+        let mut key_id = None;
+        let mut algorithm = None;
+        let mut headers = None;
+        let mut signature = None;
+
+        for param in s.split(',') {
+            let (name, value) = param.trim().split_once('=')
+                .ok_or(Error::MalformedSignature("expected name=\"value\"".into()))?;
+
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+                .ok_or(Error::MalformedSignature("value is not quoted".into()))?;
+
+            match name {
+                "keyId" => key_id = Some(value.to_owned()),
+                "algorithm" => algorithm = Some(value.to_owned()),
+                "headers" => headers = Some(value.split(' ').map(|h| h.to_lowercase()).collect()),
+                "signature" => signature = Some(base64::decode(value)
+                    .map_err(|_| Error::MalformedSignature("signature is not base64".into()))?),
+                _ => ()
+            }
+        }
+
+        Ok(Signature {
+            key_id: key_id.ok_or(Error::MalformedSignature("keyId is missing".into()))?,
+            algorithm,
+            // The spec says that only the date is signed if headers is not given
+            headers: headers.unwrap_or_else(|| vec!["date".into()]),
+            signature: signature.ok_or(Error::MalformedSignature("signature is missing".into()))?,
+        })
+    }
+}
+
+impl Signature {
+    /// Verify the signature against a request.
+    ///
+    /// `get_header` must return the value of a (lowercase) header name, if present. The `date`
+    /// header must be signed and within [MAX_CLOCK_SKEW] of the current time, and if the request
+    /// has a body, `digest` must be signed as well.
+    pub fn verify(
+        &self,
+        method: &str,
+        path_and_query: &str,
+        has_body: bool,
+        get_header: impl Fn(&str) -> Option<String>,
+        public_key_pem: &str,
+    ) -> Result<()> {
+        match self.algorithm.as_deref() {
+            // hs2019 is the algorithm name in newer drafts, and is used by some servers for RSA
+            None | Some("rsa-sha256") | Some("hs2019") => (),
+            Some(other) => return Err(Error::UnsupportedAlgorithm(other.to_owned())),
+        }
+
+        if !self.headers.iter().any(|h| h == "date") {
+            return Err(Error::MissingHeader("date (in signature)".into()));
+        }
+
+        if has_body && !self.headers.iter().any(|h| h == "digest") {
+            return Err(Error::MissingHeader("digest (in signature)".into()));
+        }
+
+        let date = get_header("date").ok_or(Error::MissingHeader("date".into()))?;
+        let date = httpdate::parse_http_date(&date).map_err(|_| Error::InvalidDate)?;
+        let now = SystemTime::now();
+
+        let skew = now.duration_since(date)
+            .or_else(|_| date.duration_since(now))
+            .map_err(|_| Error::InvalidDate)?;
+
+        if skew > MAX_CLOCK_SKEW {
+            return Err(Error::InvalidDate);
+        }
+
+        let public_key = RsaPublicKey::from_public_key_pem(public_key_pem)
+            .map_err(|e| Error::Key(e.to_string()))?;
+
+        let to_verify = signing_string(method, path_and_query, &self.headers[..], get_header)?;
+
+        public_key.verify(
+            PaddingScheme::new_pkcs1v15_sign::<Sha256>(),
+            &Sha256::digest(to_verify.as_bytes()),
+            &self.signature,
+        ).map_err(|_| Error::BadSignature)
+    }
+}
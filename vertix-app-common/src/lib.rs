@@ -1,4 +1,4 @@
-use std::{env, str::FromStr, path::PathBuf};
+use std::{env, str::FromStr, path::PathBuf, time::Duration};
 use url::Url;
 
 mod urls;
@@ -9,6 +9,10 @@ pub use error::Error;
 use error::Result;
 
 pub mod helpers;
+pub mod content;
+pub mod http_signatures;
+pub mod storage;
+pub mod media;
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -17,6 +21,16 @@ pub struct Config {
     pub domain: String,
     pub base_url: Url,
     pub trusted_certificate_files: Vec<PathBuf>,
+    pub media_dir: PathBuf,
+
+    /// How long after a remote account was last fetched it should be fetched again.
+    pub account_max_age: Duration,
+
+    /// How often to look for stale remote accounts to refresh. Never zero.
+    pub account_refresh_interval: Duration,
+
+    /// How many stale accounts to refresh on each domain each time.
+    pub account_refresh_per_domain: u32,
 }
 
 impl Config {
@@ -45,12 +59,33 @@ impl Config {
             .map(|value| value.split(",").map(|s| s.into()).collect())
             .unwrap_or_else(|_| vec![]);
 
+        let media_dir = env::var("VERTIX_MEDIA_DIR")
+            .unwrap_or_else(|_| "media".into())
+            .into();
+
+        let account_max_age = Duration::from_secs(
+            env_parse("VERTIX_ACCOUNT_MAX_AGE", 24 * 60 * 60)?);
+
+        let account_refresh_interval = Duration::from_secs(
+            env_parse("VERTIX_ACCOUNT_REFRESH_INTERVAL", 60 * 60)?);
+
+        if account_refresh_interval.is_zero() {
+            return Err(Error::InternalError(
+                "Invalid VERTIX_ACCOUNT_REFRESH_INTERVAL: must be at least 1 second".into()));
+        }
+
+        let account_refresh_per_domain = env_parse("VERTIX_ACCOUNT_REFRESH_PER_DOMAIN", 10)?;
+
         Ok(Config {
             host,
             port,
             domain,
             base_url,
             trusted_certificate_files,
+            media_dir,
+            account_max_age,
+            account_refresh_interval,
+            account_refresh_per_domain,
         })
     }
 
@@ -58,3 +93,12 @@ impl Config {
         self.base_url.make_relative(url).is_some()
     }
 }
+
+/// Parse an environment variable, falling back to a default if it isn't set.
+fn env_parse<T: FromStr>(name: &str, default: T) -> Result<T> {
+    match env::var(name) {
+        Ok(value) => T::from_str(&value)
+            .map_err(|_| Error::InternalError(format!("Invalid {name}: {value}").into())),
+        Err(_) => Ok(default)
+    }
+}
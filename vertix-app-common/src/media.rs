@@ -0,0 +1,62 @@
+//! Which kinds of files can be uploaded as media attachments.
+//!
+//! Uploaded files are served from our own origin, so anything that a browser could run as a page
+//! (HTML, SVG, PDF, ...) must be kept out. Only raster images, video and audio are accepted, and
+//! only if the contents of the file look like the type it claims to be.
+
+/// Checks whether a file looks like what it claims to be, by its magic bytes.
+type MagicCheck = fn(&[u8]) -> bool;
+
+/// Media types that can be uploaded, each with a check of the file's magic bytes.
+const ALLOWED_MEDIA_TYPES: &[(&str, MagicCheck)] = &[
+    ("image/png", |data| data.starts_with(b"\x89PNG\r\n\x1a\n")),
+    ("image/jpeg", |data| data.starts_with(&[0xff, 0xd8, 0xff])),
+    ("image/gif", |data| data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")),
+    ("image/webp", |data| is_riff(data, b"WEBP")),
+    ("video/mp4", is_iso_media),
+    ("video/webm", is_ebml),
+    ("video/ogg", is_ogg),
+    ("audio/mp4", is_iso_media),
+    ("audio/webm", is_ebml),
+    ("audio/ogg", is_ogg),
+    ("audio/mpeg", is_mpeg_audio),
+    ("audio/wav", |data| is_riff(data, b"WAVE")),
+    ("audio/flac", |data| data.starts_with(b"fLaC")),
+];
+
+/// Check that a file can be uploaded as the media type: that the type is allowed, and that the
+/// contents match it.
+pub fn is_allowed(media_type: &str, data: &[u8]) -> bool {
+    ALLOWED_MEDIA_TYPES.iter()
+        .any(|(allowed, matches)| *allowed == media_type && matches(data))
+}
+
+/// Whether browsers can be left to show a file of the media type inline. Anything else should be
+/// served as a download.
+pub fn is_inline_safe(media_type: &str) -> bool {
+    ALLOWED_MEDIA_TYPES.iter().any(|(allowed, _)| *allowed == media_type)
+}
+
+/// RIFF container with the given form type, e.g. WebP or WAV.
+fn is_riff(data: &[u8], form_type: &[u8; 4]) -> bool {
+    data.len() >= 12 && data.starts_with(b"RIFF") && data[8..12] == *form_type
+}
+
+/// ISO base media file, e.g. MP4, which starts with an `ftyp` box.
+fn is_iso_media(data: &[u8]) -> bool {
+    data.len() >= 12 && data[4..8] == *b"ftyp"
+}
+
+/// EBML, which WebM is based on.
+fn is_ebml(data: &[u8]) -> bool {
+    data.starts_with(&[0x1a, 0x45, 0xdf, 0xa3])
+}
+
+fn is_ogg(data: &[u8]) -> bool {
+    data.starts_with(b"OggS")
+}
+
+/// MP3, either with an ID3 tag or starting right at a frame.
+fn is_mpeg_audio(data: &[u8]) -> bool {
+    data.starts_with(b"ID3") || (data.len() >= 2 && data[0] == 0xff && data[1] & 0xe0 == 0xe0)
+}
@@ -0,0 +1,76 @@
+//! Storage for the files of local media attachments.
+
+use std::io;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use crate::error::Result;
+
+/// Somewhere to keep files by key. Keys are the keys of the media attachments they belong to.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Store a file, replacing any file already stored under the key.
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// Get a stored file. A missing file gives an error that
+    /// [is_not_found](crate::Error::is_not_found).
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Remove a stored file. Does nothing if there isn't one.
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Keeps files in a directory on the local filesystem, which is created if it doesn't exist.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> LocalStorage {
+        LocalStorage { root: root.into() }
+    }
+
+    /// Get the path of the file for a key. Only keys that are safe to use as file names are
+    /// accepted.
+    fn path_for(&self, key: &str) -> Result<PathBuf> {
+        let valid = !key.is_empty() &&
+            key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if valid {
+            Ok(self.root.join(key))
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("invalid storage key: {key:?}")).into())
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.path_for(key)?;
+
+        tokio::fs::create_dir_all(&self.root).await?;
+
+        // Write to a temporary file first, so that a partly written file is never served
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        Ok(tokio::fs::read(self.path_for(key)?).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path_for(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
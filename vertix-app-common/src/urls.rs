@@ -1,5 +1,5 @@
 use aragog::DatabaseAccess;
-use vertix_model::{RecordCache, Account, Note, Document};
+use vertix_model::{RecordCache, Account, Note, Document, Pagination};
 use vertix_model::activitystreams::UrlFor;
 use crate::error::*;
 use url::Url;
@@ -61,6 +61,13 @@ macro_rules! url_for_account_suffix_impl {
     });
 }
 
+/// Add the cursors of a page to a collection page url.
+fn with_pagination(mut url: Url, page: &Pagination) -> Result<Url> {
+    let query = page.to_query();
+    url.set_query(if query.is_empty() { None } else { Some(&query) });
+    Ok(url)
+}
+
 #[async_trait]
 impl<'a, D> UrlFor for Urls<'a, D>
 where
@@ -89,9 +96,10 @@ where
             format("users/{username}/outbox") remote(outbox))
     }
 
-    async fn url_for_account_outbox_page(&self, key: &str, page: u32) -> Result<Url> {
-        url_for_account_suffix_impl!(url_for_account_outbox_page(self, key) =
-            format("users/{username}/outbox/page/{page}"))
+    async fn url_for_account_outbox_page(&self, key: &str, page: &Pagination) -> Result<Url> {
+        let url: Result<Url> = url_for_account_suffix_impl!(url_for_account_outbox_page(self, key) =
+            format("users/{username}/outbox/page"));
+        with_pagination(url?, page)
     }
 
     async fn url_for_account_followers(&self, key: &str) -> Result<Url> {
@@ -99,9 +107,10 @@ where
             format("users/{username}/followers") remote(followers))
     }
 
-    async fn url_for_account_followers_page(&self, key: &str, page: u32) -> Result<Url> {
-        url_for_account_suffix_impl!(url_for_account_followers_page(self, key) =
-            format("users/{username}/followers/page/{page}"))
+    async fn url_for_account_followers_page(&self, key: &str, page: &Pagination) -> Result<Url> {
+        let url: Result<Url> = url_for_account_suffix_impl!(url_for_account_followers_page(self, key) =
+            format("users/{username}/followers/page"));
+        with_pagination(url?, page)
     }
 
     async fn url_for_account_following(&self, key: &str) -> Result<Url> {
@@ -109,9 +118,27 @@ where
             format("users/{username}/following") remote(following))
     }
 
-    async fn url_for_account_following_page(&self, key: &str, page: u32) -> Result<Url> {
-        url_for_account_suffix_impl!(url_for_account_following_page(self, key) =
-            format("users/{username}/following/page/{page}"))
+    async fn url_for_account_following_page(&self, key: &str, page: &Pagination) -> Result<Url> {
+        let url: Result<Url> = url_for_account_suffix_impl!(url_for_account_following_page(self, key) =
+            format("users/{username}/following/page"));
+        with_pagination(url?, page)
+    }
+
+    async fn url_for_account_public_key(&self, key: &str) -> Result<Url> {
+        let account = self.account_cache.get(key, self.db).await?;
+
+        if let Some(ref remote) = account.remote {
+            if let Some(ref public_key) = remote.public_key {
+                Ok(public_key.id.clone())
+            } else {
+                Err(Error::InternalError("can't url_for_account_public_key on remote account \
+                    because remote.public_key is missing".into()))
+            }
+        } else {
+            let mut url = self.url_for_account(key).await?;
+            url.set_fragment(Some("main-key"));
+            Ok(url)
+        }
     }
 
     async fn url_for_note(&self, key: &str) -> Result<Url> {
@@ -129,6 +156,28 @@ where
         }
     }
 
+    async fn url_for_note_replies(&self, key: &str) -> Result<Url> {
+        let mut url = self.url_for_note(key).await?;
+        url.set_path(&format!("{}/replies", url.path()));
+        Ok(url)
+    }
+
+    async fn url_for_note_replies_page(&self, key: &str, page: &Pagination) -> Result<Url> {
+        let mut url = self.url_for_note(key).await?;
+        url.set_path(&format!("{}/replies/page", url.path()));
+        with_pagination(url, page)
+    }
+
+    fn url_for_hashtag(&self, name: &str) -> Result<Url> {
+        let name = encode(name);
+        Ok(self.base_url.join(&format!("api/v1/timelines/tag/{name}"))?)
+    }
+
+    fn url_for_media_attachment(&self, key: &str) -> Result<Url> {
+        let key = encode(key);
+        Ok(self.base_url.join(&format!("media/{key}"))?)
+    }
+
     fn url_for_shared_inbox(&self) -> Result<Url> {
         Ok(self.base_url.join("inbox")?)
     }
@@ -0,0 +1,106 @@
+use vertix_app_common::content::{Links, render_markdown, render_plain_text, render_profile};
+use vertix_app_common::helpers::{parse_mentions, parse_hashtags, ParsedMention};
+use vertix_model::{Profile, ProfileField, ProfileSource, sanitize_html};
+
+fn mention(name: &str, username: &str, domain: Option<&str>) -> ParsedMention {
+    ParsedMention {
+        name: name.into(),
+        username: username.into(),
+        domain: domain.map(|d| d.into()),
+    }
+}
+
+#[test]
+fn parse_local_and_remote_mentions() {
+    assert_eq!(parse_mentions("@alice, have you met @bob@example.com?"), vec![
+        mention("@alice", "alice", None),
+        mention("@bob@example.com", "bob", Some("example.com")),
+    ]);
+}
+
+#[test]
+fn parse_mentions_skips_duplicates_and_email_addresses() {
+    assert_eq!(parse_mentions("@Carol @carol, mail carol@example.com or @dave@Example.com. \
+        @dave@example.com"), vec![
+        mention("@Carol", "Carol", None),
+        mention("@dave@Example.com", "dave", Some("Example.com")),
+    ]);
+}
+
+#[test]
+fn parse_hashtags_skips_anchors_and_entities() {
+    assert_eq!(parse_hashtags("#Rust is fun. See http://example.com/#top &#39; #rust_lang"),
+        vec!["Rust".to_owned(), "rust_lang".to_owned()]);
+}
+
+fn links() -> Links {
+    let mut links = Links::default();
+    links.mentions.insert("@bob@example.com".into(),
+        "https://example.com/users/bob".parse().unwrap());
+    links.hashtags.insert("rust".into(),
+        "https://vertix.example/api/v1/timelines/tag/rust".parse().unwrap());
+    links
+}
+
+#[test]
+fn render_plain_text_escapes_and_links() {
+    let html = render_plain_text(
+        "Hi @bob@example.com & @nobody <b>\nsee https://example.org/\n\n#Rust", &links());
+
+    assert!(html.starts_with(
+        r#"<p>Hi <a href="https://example.com/users/bob" class="mention""#));
+    assert!(html.contains("&amp; @nobody &lt;b&gt;<br>"));
+    assert!(html.contains(r#"<a href="https://example.org/" rel="nofollow noopener noreferrer">"#));
+    assert!(html.contains(
+        r#"</p><p><a href="https://vertix.example/api/v1/timelines/tag/rust" class="hashtag""#));
+}
+
+#[test]
+fn render_markdown_links_outside_of_code() {
+    let html = render_markdown(
+        "**Hi** @bob@example.com\n\n`@bob@example.com` <script>alert(1)</script>", &links());
+
+    assert!(html.contains(r#"<strong>Hi</strong> <a href="https://example.com/users/bob""#));
+    assert!(html.contains("<code>@bob@example.com</code>"));
+    assert!(!html.contains("script"));
+}
+
+#[test]
+fn sanitize_remote_html() {
+    let html = sanitize_html(concat!(
+        r#"<p onclick="x()">Hi <img src="x"><a href="javascript:x()">there</a>"#,
+        r#"<a href="/relative">!</a></p>"#,
+    ));
+
+    assert!(html.starts_with("<p>Hi <a"));
+    assert!(!html.contains("onclick"));
+    assert!(!html.contains("img"));
+    assert!(!html.contains("javascript"));
+    assert!(!html.contains("/relative"));
+}
+
+#[test]
+fn render_profile_from_source() {
+    let mut profile = Profile {
+        source: Some(ProfileSource {
+            summary: "I <3 Rust\n\nhttps://example.org/".into(),
+            fields: vec![
+                ProfileField { name: "Website".into(), value: "https://example.org/".into() },
+            ],
+        }),
+        ..Profile::default()
+    };
+
+    render_profile(&mut profile);
+
+    let summary = profile.summary.expect("summary should be rendered");
+    assert!(summary.starts_with("<p>I &lt;3 Rust</p><p><a href=\"https://example.org/\""));
+    assert_eq!(profile.fields.len(), 1);
+    assert!(profile.fields[0].value.starts_with("<a href=\"https://example.org/\""));
+
+    profile.source = Some(ProfileSource::default());
+    render_profile(&mut profile);
+
+    assert_eq!(profile.summary, None);
+    assert!(profile.fields.is_empty());
+}
@@ -0,0 +1,128 @@
+use std::net::TcpListener;
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use anyhow::Result;
+use rsa::RsaPrivateKey;
+use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
+use url::Url;
+use vertix_app_common::http_signatures::{self, Signature};
+
+const KEY_ID: &str = "https://example.com/users/test#main-key";
+
+struct Keypair {
+    public_key_pem: String,
+    private_key_pem: String,
+}
+
+fn generate_keypair() -> Result<Keypair> {
+    // Small key so that the tests don't spend forever finding primes
+    let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+
+    Ok(Keypair {
+        public_key_pem: private_key.to_public_key().to_public_key_pem(LineEnding::LF)?,
+        private_key_pem: private_key.to_pkcs8_pem(LineEnding::LF)?.to_string(),
+    })
+}
+
+/// Stand-in for a remote inbox, which accepts only requests signed with `public_key_pem`.
+async fn inbox(req: HttpRequest, body: web::Bytes, public_key_pem: web::Data<String>)
+    -> HttpResponse
+{
+    let get_header = |name: &str| req.headers().get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_owned());
+
+    let result = (|| {
+        let digest = get_header("digest")
+            .ok_or(http_signatures::Error::MissingHeader("digest".into()))?;
+        http_signatures::verify_digest(&digest, &body)?;
+
+        let signature: Signature = get_header("signature")
+            .ok_or(http_signatures::Error::MissingHeader("signature".into()))?
+            .parse()?;
+
+        assert_eq!(signature.key_id, KEY_ID);
+
+        let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+        signature.verify(req.method().as_str(), path_and_query, true, get_header, &public_key_pem)
+    })();
+
+    match result {
+        Ok(()) => HttpResponse::Accepted().finish(),
+        Err(err) => HttpResponse::Unauthorized().body(err.to_string()),
+    }
+}
+
+/// Start the stand-in inbox and return its url.
+fn start_inbox(public_key_pem: String) -> Result<Url> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(public_key_pem.clone()))
+            .route("/users/test/inbox", web::post().to(inbox))
+    })
+        .workers(1)
+        .listen(listener)?
+        .run();
+
+    actix_rt::spawn(server);
+
+    Ok(Url::parse(&format!("http://127.0.0.1:{port}/users/test/inbox"))?)
+}
+
+#[actix_rt::test]
+async fn signed_post_is_accepted() -> Result<()> {
+    let keypair = generate_keypair()?;
+    let inbox_url = start_inbox(keypair.public_key_pem)?;
+
+    let res = http_signatures::signed_post(&reqwest::Client::new(), inbox_url,
+        br#"{"type":"Follow"}"#.to_vec(), KEY_ID, &keypair.private_key_pem)?
+        .send().await?;
+
+    assert_eq!(res.status().as_u16(), 202, "{}", res.text().await?);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn post_signed_by_another_key_is_rejected() -> Result<()> {
+    let keypair = generate_keypair()?;
+    let other_keypair = generate_keypair()?;
+    let inbox_url = start_inbox(keypair.public_key_pem)?;
+
+    let res = http_signatures::signed_post(&reqwest::Client::new(), inbox_url,
+        br#"{"type":"Follow"}"#.to_vec(), KEY_ID, &other_keypair.private_key_pem)?
+        .send().await?;
+
+    assert_eq!(res.status().as_u16(), 401);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn post_with_tampered_body_is_rejected() -> Result<()> {
+    let keypair = generate_keypair()?;
+    let inbox_url = start_inbox(keypair.public_key_pem)?;
+
+    let res = http_signatures::signed_post(&reqwest::Client::new(), inbox_url,
+        br#"{"type":"Follow"}"#.to_vec(), KEY_ID, &keypair.private_key_pem)?
+        .body(r#"{"type":"Delete"}"#)
+        .send().await?;
+
+    assert_eq!(res.status().as_u16(), 401);
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn unsigned_post_is_rejected() -> Result<()> {
+    let keypair = generate_keypair()?;
+    let inbox_url = start_inbox(keypair.public_key_pem)?;
+
+    let res = reqwest::Client::new().post(inbox_url)
+        .body(r#"{"type":"Follow"}"#)
+        .send().await?;
+
+    assert_eq!(res.status().as_u16(), 401);
+    Ok(())
+}
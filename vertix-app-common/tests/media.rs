@@ -0,0 +1,36 @@
+use vertix_app_common::media::{is_allowed, is_inline_safe};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+const JPEG: &[u8] = &[0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+const WEBP: &[u8] = b"RIFF\x24\0\0\0WEBPVP8 ";
+const MP4: &[u8] = b"\0\0\0\x20ftypisom\0\0\x02\0";
+const MP3: &[u8] = b"ID3\x04\0\0\0\0\0\0";
+
+#[test]
+fn allowed_media_must_match_their_type() {
+    assert!(is_allowed("image/png", PNG));
+    assert!(is_allowed("image/jpeg", JPEG));
+    assert!(is_allowed("image/webp", WEBP));
+    assert!(is_allowed("video/mp4", MP4));
+    assert!(is_allowed("audio/mpeg", MP3));
+
+    // Claiming to be something else
+    assert!(!is_allowed("image/png", JPEG));
+    assert!(!is_allowed("image/jpeg", b"<html><script>alert(1)</script>"));
+    assert!(!is_allowed("audio/wav", WEBP));
+
+    // Too short to tell
+    assert!(!is_allowed("video/mp4", b"\0\0\0\x20ftyp"));
+    assert!(!is_allowed("image/png", b""));
+}
+
+#[test]
+fn scriptable_media_types_are_not_allowed() {
+    for media_type in ["text/html", "image/svg+xml", "application/pdf", "text/xml"] {
+        assert!(!is_allowed(media_type, PNG));
+        assert!(!is_inline_safe(media_type));
+    }
+
+    assert!(is_inline_safe("image/png"));
+    assert!(is_inline_safe("video/webm"));
+}
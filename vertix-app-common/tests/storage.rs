@@ -0,0 +1,32 @@
+use anyhow::Result;
+use vertix_app_common::storage::{Storage, LocalStorage};
+
+#[actix_rt::test]
+async fn local_storage_put_get_delete() -> Result<()> {
+    let root = std::env::temp_dir().join(format!("vertix-storage-{}", rand::random::<u64>()));
+    let storage = LocalStorage::new(&root);
+
+    storage.put("file1", b"hello").await?;
+    assert_eq!(storage.get("file1").await?, b"hello");
+
+    storage.put("file1", b"replaced").await?;
+    assert_eq!(storage.get("file1").await?, b"replaced");
+
+    storage.delete("file1").await?;
+    assert!(storage.get("file1").await.unwrap_err().is_not_found());
+
+    // Deleting again is fine
+    storage.delete("file1").await?;
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn local_storage_rejects_unsafe_keys() {
+    let storage = LocalStorage::new(std::env::temp_dir().join("vertix-storage-unsafe"));
+
+    for key in ["", "../file", "dir/file", "file.tmp"] {
+        assert!(storage.put(key, b"data").await.is_err(), "{key:?} was accepted");
+    }
+}
@@ -6,6 +6,9 @@ use crate::{SingleExchangeMessage, error::Result, macros::setup_exchange};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeliverActivity {
+    /// Key of the local account the activity is sent on behalf of. The request is signed with
+    /// this account's key.
+    pub from_account: String,
     pub inbox: Url,
     pub activity: ActivityBox,
 }
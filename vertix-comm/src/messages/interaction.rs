@@ -5,7 +5,7 @@ use lapin::protocol::basic::AMQPProperties;
 
 use serde::{Serialize, Deserialize};
 use futures::stream::{Stream, TryStreamExt};
-use vertix_model::{Note, Recipient, Document, Edge, Follow};
+use vertix_model::{Account, Note, Recipient, Document, Edge, Follow, Like, Share};
 
 use crate::{SingleExchangeMessage, ReceiveMessage, macros::setup_exchange, error::Result};
 
@@ -20,12 +20,29 @@ use crate::{SingleExchangeMessage, ReceiveMessage, macros::setup_exchange, error
 /// | `v-from-acct-{key}`    | true if account {key} is the sender/initiator              |
 /// | `v-to-public`          | true if public is included in recipients                   |
 /// | `v-to-acct-{key}`      | true if account {key} is included in recipients            |
+/// | `v-to-followers-{key}` | true if the followers of account {key} are in recipients   |
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "params")]
 pub enum Interaction {
+    /// The profile of the account has been changed. Contains the account after the change.
+    UpdateAccount(Document<Account>),
     Note(Document<Note>),
+    /// The note has been edited. Contains the note after the edit.
+    EditNote(Document<Note>),
+    /// The note has been deleted. Contains the note as it was before deletion.
+    DeleteNote(Document<Note>),
     InitiateFollow(Edge<Follow>),
     SetFollowAccepted(Edge<Follow>),
+    /// The follow has been deleted. Contains the follow as it was before deletion.
+    RemoveFollow(Edge<Follow>),
+    /// An account liked a note. The note is included so that its author can be notified.
+    Like { like: Edge<Like>, note: Document<Note> },
+    /// The like has been deleted. Contains the like as it was before deletion.
+    Unlike { like: Edge<Like>, note: Document<Note> },
+    /// An account shared (boosted) a note. Shares are always public.
+    Share { share: Edge<Share>, note: Document<Note> },
+    /// The share has been deleted. Contains the share as it was before deletion.
+    Unshare { share: Edge<Share>, note: Document<Note> },
 }
 
 impl SingleExchangeMessage for Interaction {
@@ -38,18 +55,35 @@ impl SingleExchangeMessage for Interaction {
 
         // v-from
         match self {
-            Interaction::Note(note) =>
+            Interaction::UpdateAccount(account) =>
+                headers.insert(format!("v-from-acct-{}", account.key()).into(), true.into()),
+            Interaction::Note(note) |
+            Interaction::EditNote(note) |
+            Interaction::DeleteNote(note) =>
                 if let Some(ref from) = note.from {
                     headers.insert(format!("v-from-acct-{from}").into(), true.into())
                 },
             Interaction::InitiateFollow(follow) |
-            Interaction::SetFollowAccepted(follow) =>
+            Interaction::SetFollowAccepted(follow) |
+            Interaction::RemoveFollow(follow) =>
                 headers.insert(format!("v-from-acct-{}", follow.key_from()).into(), true.into()),
+            Interaction::Like { like, .. } |
+            Interaction::Unlike { like, .. } =>
+                headers.insert(format!("v-from-acct-{}", like.key_from()).into(), true.into()),
+            Interaction::Share { share, .. } |
+            Interaction::Unshare { share, .. } =>
+                headers.insert(format!("v-from-acct-{}", share.key_from()).into(), true.into()),
         }
 
         // v-to-*
         match self {
-            Interaction::Note(note) => {
+            Interaction::UpdateAccount(account) => {
+                headers.insert("v-to-public".into(), true.into());
+                headers.insert(format!("v-to-followers-{}", account.key()).into(), true.into());
+            },
+            Interaction::Note(note) |
+            Interaction::EditNote(note) |
+            Interaction::DeleteNote(note) => {
                 for list in [&note.to, &note.cc, &note.bto, &note.bcc] {
                     for recipient in list {
                         match recipient {
@@ -57,13 +91,29 @@ impl SingleExchangeMessage for Interaction {
                                 headers.insert("v-to-public".into(), true.into()),
                             Recipient::Account(key) =>
                                 headers.insert(format!("v-to-acct-{key}").into(), true.into()),
+                            Recipient::Followers(key) =>
+                                headers.insert(format!("v-to-followers-{key}").into(), true.into()),
                         }
                     }
                 }
             },
             Interaction::InitiateFollow(follow) |
-            Interaction::SetFollowAccepted(follow) =>
+            Interaction::SetFollowAccepted(follow) |
+            Interaction::RemoveFollow(follow) =>
                 headers.insert(format!("v-to-acct-{}", follow.key_to()).into(), true.into()),
+            Interaction::Like { note, .. } |
+            Interaction::Unlike { note, .. } =>
+                if let Some(ref from) = note.from {
+                    headers.insert(format!("v-to-acct-{from}").into(), true.into())
+                },
+            Interaction::Share { note, .. } |
+            Interaction::Unshare { note, .. } => {
+                headers.insert("v-to-public".into(), true.into());
+
+                if let Some(ref from) = note.from {
+                    headers.insert(format!("v-to-acct-{from}").into(), true.into())
+                }
+            },
         }
 
         AMQPProperties::default().with_headers(headers)
@@ -71,6 +121,40 @@ impl SingleExchangeMessage for Interaction {
 }
 
 impl Interaction {
+    /// The note that the interaction is about, if any.
+    pub fn note(&self) -> Option<&Document<Note>> {
+        match self {
+            Interaction::Note(note) |
+            Interaction::EditNote(note) |
+            Interaction::DeleteNote(note) |
+            Interaction::Like { note, .. } |
+            Interaction::Unlike { note, .. } |
+            Interaction::Share { note, .. } |
+            Interaction::Unshare { note, .. } => Some(note),
+            Interaction::UpdateAccount(_) |
+            Interaction::InitiateFollow(_) |
+            Interaction::SetFollowAccepted(_) |
+            Interaction::RemoveFollow(_) => None,
+        }
+    }
+
+    /// Mutable version of [Interaction::note].
+    pub fn note_mut(&mut self) -> Option<&mut Document<Note>> {
+        match self {
+            Interaction::Note(note) |
+            Interaction::EditNote(note) |
+            Interaction::DeleteNote(note) |
+            Interaction::Like { note, .. } |
+            Interaction::Unlike { note, .. } |
+            Interaction::Share { note, .. } |
+            Interaction::Unshare { note, .. } => Some(note),
+            Interaction::UpdateAccount(_) |
+            Interaction::InitiateFollow(_) |
+            Interaction::SetFollowAccepted(_) |
+            Interaction::RemoveFollow(_) => None,
+        }
+    }
+
     pub async fn setup(ch: &Channel) -> Result<()> {
         setup_exchange!(ch,
             Interaction {
@@ -108,7 +192,9 @@ impl Interaction {
                 Recipient::Public =>
                     headers.insert("v-to-public".into(), true.into()),
                 Recipient::Account(key) =>
-                    headers.insert(format!("v-to-acct-{key}").into(), true.into())
+                    headers.insert(format!("v-to-acct-{key}").into(), true.into()),
+                Recipient::Followers(key) =>
+                    headers.insert(format!("v-to-followers-{key}").into(), true.into()),
             }
         }
 
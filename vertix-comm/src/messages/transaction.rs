@@ -1,7 +1,19 @@
 use lapin::{Channel, publisher_confirm::PublisherConfirm};
 use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
 use url::Url;
-use vertix_model::{Note, Account, Follow, Edge, Document};
+use vertix_model::{
+    Note,
+    NoteSource,
+    MediaAttachment,
+    Account,
+    Profile,
+    Follow,
+    Like,
+    Share,
+    Edge,
+    Document,
+};
 
 use crate::{
     error::{Error, Result},
@@ -19,10 +31,55 @@ pub struct Transaction {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "params")]
 pub enum Action {
+    /// Register a new local account. The password must already be hashed, so that it is never
+    /// sent or logged in the clear.
+    RegisterAccount {
+        username: String,
+        password_hash: String,
+    },
     /// Get or update a remote account.
     FetchAccount(Url),
+    /// Record that remote accounts, by key, are about to be refreshed, so that they aren't queued
+    /// again until they're stale again. Send this separately from the FetchAccount, so that it's
+    /// kept even if the fetch fails.
+    RecordRefreshAttempts(Vec<String>),
+    /// Replace the profile of a local account. Does nothing if it's the same.
+    UpdateProfile {
+        account: String,
+        profile: Profile,
+    },
+    /// Update a remote account with data received from its server. `account.remote` must be set.
+    /// Does nothing if we don't have the account.
+    UpdateRemoteAccount(Account),
+    /// Get a remote note, along with its author if we don't have them yet. Does not update the
+    /// note if we already have it.
+    FetchNote(Url),
+    /// Record an uploaded media attachment under a key from
+    /// [MediaAttachment::generate_key]. The file must already be in storage under the same key.
+    CreateMediaAttachment {
+        key: String,
+        media_attachment: MediaAttachment,
+    },
     /// Publish a note.
     PublishNote(Note),
+    /// Store a note received from a remote server. `note.from` must be set to the author, and
+    /// `note.remote` must be set. Does nothing if we already have a note with the same uri.
+    ReceiveNote(Note),
+    /// Change the content of a note, keeping the previous content as a revision. `source` is only
+    /// set for local notes, and `updated_at` is only used for remote notes. The hashtags are
+    /// replaced with `hashtags`. Does nothing if the content, source and hashtags are the same.
+    EditNote {
+        key: String,
+        content: String,
+        #[serde(default)]
+        source: Option<NoteSource>,
+        #[serde(default)]
+        hashtags: Vec<String>,
+        updated_at: Option<DateTime<Utc>>,
+    },
+    /// Delete a note by key. Local notes are replaced with a tombstone, and remote notes are
+    /// removed. Does nothing if the note has already been deleted.
+    DeleteNote(String),
     /// Start a follow between two accounts.
     InitiateFollow {
         from_account: String,
@@ -34,6 +91,33 @@ pub enum Action {
         key: String,
         accepted: bool,
     },
+    /// End a follow between two accounts. Does nothing if there isn't one.
+    RemoveFollow {
+        from_account: String,
+        to_account: String,
+    },
+    /// Like a note from an account.
+    LikeNote {
+        account: String,
+        note: String,
+        uri: Option<Url>,
+    },
+    /// Remove a like of a note by an account. Does nothing if there isn't one.
+    UnlikeNote {
+        account: String,
+        note: String,
+    },
+    /// Share (boost) a note from an account.
+    ShareNote {
+        account: String,
+        note: String,
+        uri: Option<Url>,
+    },
+    /// Remove a share of a note by an account. Does nothing if there isn't one.
+    UnshareNote {
+        account: String,
+        note: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,10 +128,24 @@ pub struct TransactionResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "body")]
 pub enum ActionResponse {
+    RegisterAccount(Document<Account>),
     FetchAccount(Document<Account>),
+    RecordRefreshAttempts,
+    UpdateProfile { modified: bool, account: Document<Account> },
+    UpdateRemoteAccount(Option<Document<Account>>),
+    FetchNote(Document<Note>),
+    CreateMediaAttachment(Document<MediaAttachment>),
     PublishNote(Document<Note>),
+    ReceiveNote { created: bool, note: Document<Note> },
+    EditNote { modified: bool, note: Document<Note> },
+    DeleteNote { deleted: bool, note: Document<Note> },
     InitiateFollow { created: bool, follow: Edge<Follow> },
     SetFollowAccepted { modified: bool, follow: Edge<Follow> },
+    RemoveFollow { removed: Option<Edge<Follow>> },
+    LikeNote { created: bool, like: Edge<Like> },
+    UnlikeNote { removed: Option<Edge<Like>> },
+    ShareNote { created: bool, share: Edge<Share> },
+    UnshareNote { removed: Option<Edge<Share>> },
 }
 
 impl SingleExchangeMessage for Transaction {
@@ -0,0 +1,95 @@
+use aragog::{compare, DatabaseAccess, Record};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{Document, Error, Wrap};
+
+/// Number of random bytes in a generated token.
+pub const ACCESS_TOKEN_BYTES: usize = 32;
+
+/// How long an access token is valid for after it is issued.
+pub const ACCESS_TOKEN_LIFETIME_DAYS: i64 = 90;
+
+/// A bearer token issued to a local account when it logs in.
+///
+/// Only a hash of the token is stored, so the token itself is only ever known to the client it was
+/// issued to.
+#[derive(Clone, Serialize, Deserialize, Record)]
+#[before_create(func = "before_create")]
+pub struct AccessToken {
+    /// Account key
+    pub account: String,
+
+    /// SHA-256 hash of the token, base64 encoded.
+    pub token_hash: String,
+
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl AccessToken {
+    /// Issue a new token for an account. Returns the token, which must be given to the client
+    /// now as it can't be recovered later, along with the stored record.
+    pub async fn issue<D>(
+        account_key: &str,
+        db: &D
+    ) -> Result<(String, Document<AccessToken>), Error>
+    where
+        D: DatabaseAccess,
+    {
+        let mut bytes = [0u8; ACCESS_TOKEN_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        let token = base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+        let access_token = AccessToken::create(AccessToken {
+            account: account_key.to_owned(),
+            token_hash: hash_token(&token),
+            created_at: None,
+            expires_at: Some(Utc::now() + Duration::days(ACCESS_TOKEN_LIFETIME_DAYS)),
+        }, db).await?;
+
+        Ok((token, access_token.wrap()))
+    }
+
+    /// Find the record for a token that a client presented. Expired tokens are not found.
+    pub async fn find_by_token<D>(token: &str, db: &D) -> Result<Document<AccessToken>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        AccessToken::get(
+            &AccessToken::query()
+                .bind_var("token_hash", hash_token(token))
+                .filter(compare!(field "token_hash").equals("@token_hash").into()),
+            db,
+        )
+        .await?
+        .first_record()
+        .wrap()
+        .filter(|access_token: &Document<AccessToken>| !access_token.is_expired())
+        .ok_or_else(|| Error::NotFound {
+            model: "AccessToken".into(),
+            // Don't leak the token into logs
+            params: json!({})
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.map(|expires_at| expires_at <= Utc::now()).unwrap_or(false)
+    }
+
+    fn before_create(&mut self) -> Result<(), aragog::Error> {
+        self.created_at = Some(Utc::now());
+        Ok(())
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    base64::encode(Sha256::digest(token.as_bytes()))
+}
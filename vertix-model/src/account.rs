@@ -1,26 +1,69 @@
 use crate::{
     Error,
     Note,
-    PageLimit,
-    ApplyPageLimit,
-    activitystreams::{ToObject, UrlFor},
+    AccountCredential,
+    Pagination,
+    Page,
+    CursorItem,
+    TimelineEntry,
+    TimelineOptions,
+    AccountKey,
+    Profile,
+    ProfileField,
+    sanitize_html,
+    activitystreams::{
+        ToObject,
+        UrlFor,
+        ActorObject,
+        PublicKey,
+        PublicKeyExtension,
+        AttachmentObject,
+        PropertyValue,
+        get_image,
+        get_property_values,
+    },
+    visibility::{can_view_aql, viewer_bind_vars},
     Document,
     Wrap
 };
 use async_trait::async_trait;
 use activitystreams::{
+    BaseBox,
     ext::Ext,
     actor::{Person, properties::ApActorProperties},
     endpoint::EndpointProperties
 };
 use aragog::{compare, DatabaseAccess, Record};
-use aragog::query::{Query, SortDirection};
 use chrono::{DateTime, Utc, FixedOffset};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use maplit::hashmap;
 use url::Url;
 
+/// Minimum length of a local username.
+pub const USERNAME_MIN_LENGTH: usize = 1;
+
+/// Maximum length of a local username.
+pub const USERNAME_MAX_LENGTH: usize = 30;
+
+/// Usernames that can't be registered, because they would be confusing or collide with paths.
+pub const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "root",
+    "inbox",
+    "outbox",
+    "api",
+    "users",
+    "notes",
+    "support",
+    "help",
+    "security",
+    "postmaster",
+    "webmaster",
+    "abuse",
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize, Record)]
 #[before_create(func = "before_create")]
 #[before_save(func = "before_save")]
@@ -29,6 +72,11 @@ pub struct Account {
     /// `@{username}@{domain}`
     pub username: String,
 
+    /// The username in lowercase, which must be unique per domain. This is set automatically on
+    /// save, and is what usernames are looked up by.
+    #[serde(default)]
+    pub username_normalized: Option<String>,
+
     /// The domain at which the user resides. None if local.
     #[serde(default)]
     pub domain: Option<String>,
@@ -37,6 +85,13 @@ pub struct Account {
     #[serde(default)]
     pub remote: Option<RemoteAccountInfo>,
 
+    /// PEM encoded public key of a local account. The private key is kept in [AccountKey].
+    #[serde(default)]
+    pub public_key_pem: Option<String>,
+
+    #[serde(flatten)]
+    pub profile: Profile,
+
     #[serde(default)]
     pub created_at: Option<DateTime<Utc>>,
 
@@ -53,10 +108,19 @@ pub struct RemoteAccountInfo {
     #[serde(default)]
     pub last_fetched_at: Option<DateTime<Utc>>,
 
+    /// The last time a refresh of the remote user was queued, whether or not it worked. Accounts
+    /// that can't be fetched are only tried again once this is stale too.
+    #[serde(default)]
+    pub last_refresh_attempted_at: Option<DateTime<Utc>>,
+
     /// Inbox url for remote user.
     #[serde(default)]
     pub inbox: Option<Url>,
 
+    /// Shared inbox url for the remote user's server, if it has one.
+    #[serde(default)]
+    pub shared_inbox: Option<Url>,
+
     /// Outbox url for remote user.
     #[serde(default)]
     pub outbox: Option<Url>,
@@ -68,7 +132,19 @@ pub struct RemoteAccountInfo {
     /// Following url for remote user.
     #[serde(default)]
     pub following: Option<Url>,
-    
+
+    /// The public key that the remote user signs requests with.
+    #[serde(default)]
+    pub public_key: Option<RemotePublicKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemotePublicKey {
+    /// The id of the key, which signatures refer to as their `keyId`.
+    pub id: Url,
+
+    /// PEM encoded public key.
+    pub pem: String,
 }
 
 impl Account {
@@ -76,22 +152,131 @@ impl Account {
     pub fn new(username: String) -> Account {
         Account {
             username,
+            username_normalized: None,
             domain: None,
             remote: None,
+            public_key_pem: None,
+            profile: Profile::default(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    /// Create a local account, along with a new keypair for it.
+    pub async fn create_local<D>(account: Account, db: &D) -> Result<Document<Account>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let mut account = Account::create(account, db).await?;
+
+        let account_key = AccountKey::create(AccountKey::generate(account.key()).await?, db).await?;
+
+        account.public_key_pem = Some(account_key.public_key_pem.clone());
+        account.save(db).await?;
+
+        Ok(account.wrap())
+    }
+
+    /// Check that a username is acceptable for a new local account.
+    pub fn validate_username(username: &str) -> Result<(), Error> {
+        let invalid = |reason: String| Err(Error::Validation(reason.into()));
+
+        if username.len() < USERNAME_MIN_LENGTH || username.len() > USERNAME_MAX_LENGTH {
+            return invalid(format!("Username must be between {} and {} characters long",
+                USERNAME_MIN_LENGTH, USERNAME_MAX_LENGTH));
+        }
+
+        if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return invalid("Username may only contain letters, numbers and underscores".into());
+        }
+
+        if RESERVED_USERNAMES.contains(&username.to_ascii_lowercase().as_str()) {
+            return invalid(format!("Username {username:?} is reserved"));
+        }
+
+        Ok(())
+    }
+
+    /// Register a new local account with a password that has already been hashed (see
+    /// [AccountCredential::hash_password]). Usernames are unique regardless of case.
+    pub async fn register<D>(
+        username: &str,
+        password_hash: String,
+        db: &D
+    ) -> Result<Document<Account>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Account::validate_username(username)?;
+
+        match Account::find_by_username(username, None, db).await {
+            Ok(_) => return Err(Error::UsernameTaken(username.to_owned().into())),
+            Err(e) if e.is_not_found() => (),
+            Err(e) => return Err(e),
+        }
+
+        let account = Account::create_local(Account::new(username.to_owned()), db).await?;
+
+        let credential = AccountCredential {
+            account: account.key().to_owned(),
+            password_hash,
             created_at: None,
             updated_at: None,
+        };
+
+        // Outside of a transaction nothing would roll the account back, so remove it here rather
+        // than leave behind an account that nobody can log in to
+        if let Err(e) = AccountCredential::create(credential, db).await {
+            match AccountKey::find_by_account(account.key(), db).await {
+                Ok(account_key) => account_key.delete(db).await?,
+                Err(e) if e.is_not_found() => (),
+                Err(e) => return Err(e),
+            }
+
+            account.delete(db).await?;
+            return Err(e.into());
         }
+
+        Ok(account)
     }
 
     pub fn is_local(&self) -> bool {
         self.domain.is_none()
     }
 
+    /// Whether this is a remote account that was last fetched more than `max_age` ago, or never,
+    /// and that we haven't tried to refresh within `max_age` either. Local accounts are never
+    /// stale.
+    pub fn is_stale(&self, max_age: std::time::Duration) -> bool {
+        let cutoff = stale_cutoff(max_age);
+
+        let is_old = |time: Option<DateTime<Utc>>| match (time, cutoff) {
+            (Some(time), Some(cutoff)) => time < cutoff,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+
+        match self.remote {
+            Some(ref remote) =>
+                is_old(remote.last_fetched_at) && is_old(remote.last_refresh_attempted_at),
+            None => false,
+        }
+    }
+
+    /// Replace what we know about a remote account with newer data from its server, e.g. from a
+    /// fetch or an Update. The username and domain are kept.
+    pub fn refresh_remote(&mut self, newer: Account) {
+        self.remote = newer.remote;
+        self.profile = newer.profile;
+        self.updated_at = newer.updated_at;
+    }
+
     pub fn is_remote(&self) -> bool {
         self.domain.is_some()
     }
 
-    /// Find an account by username and domain. Use domain = `None` for a local account.
+    /// Find an account by username and domain. Use domain = `None` for a local account. The
+    /// username is matched case-insensitively.
     pub async fn find_by_username<D>(
         username: &str,
         domain: Option<&str>,
@@ -102,10 +287,10 @@ impl Account {
     {
         Account::get(
             &Account::query()
-                .bind_var("username", username)
+                .bind_var("username", username.to_lowercase())
                 .bind_var("domain", domain)
                 .filter(
-                    compare!(field "username").equals("@username")
+                    compare!(field "username_normalized").equals("@username")
                         .and(compare!(field "domain").equals("@domain"))
                         .into(),
                 ),
@@ -140,71 +325,151 @@ impl Account {
         })
     }
 
-    /// Get the notes that this account has published.
+    /// Find remote accounts that are stale (see [Account::is_stale]), least recently tried first.
+    /// At most `per_domain` accounts are returned for each domain, so that refreshing them doesn't
+    /// hit any one server too hard.
+    pub async fn find_stale_remote<D>(
+        max_age: std::time::Duration,
+        per_domain: u32,
+        db: &D
+    ) -> Result<Vec<Document<Account>>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let cutoff = match stale_cutoff(max_age) {
+            Some(cutoff) => cutoff,
+            None => return Ok(vec![]),
+        };
+
+        let res: Vec<Document<Account>> = db.database()
+            .aql_bind_vars(r#"
+                WITH Account
+                FOR account IN Account
+                    FILTER account.remote != null
+                    FILTER account.remote.last_fetched_at == null
+                        OR DATE_TIMESTAMP(account.remote.last_fetched_at) < @cutoff
+                    FILTER account.remote.last_refresh_attempted_at == null
+                        OR DATE_TIMESTAMP(account.remote.last_refresh_attempted_at) < @cutoff
+                    COLLECT domain = account.domain INTO group = account
+                    FOR account IN (
+                        FOR account IN group
+                            SORT account.remote.last_refresh_attempted_at ASC,
+                                account.remote.last_fetched_at ASC
+                            LIMIT @per_domain
+                            RETURN account
+                    )
+                        SORT account.remote.last_refresh_attempted_at ASC,
+                            account.remote.last_fetched_at ASC
+                        RETURN account
+            "#, hashmap! {
+                "cutoff" => json!(cutoff.timestamp_millis()),
+                "per_domain" => json!(per_domain)
+            })
+            .await.map_err(aragog::Error::from)?;
+        Ok(res)
+    }
+
+    /// Record that a refresh of remote accounts has been queued, by key, so that they aren't
+    /// queued again until they're stale again, even if fetching them fails. Local accounts are
+    /// left alone.
+    pub async fn record_refresh_attempts<D>(keys: &[String], db: &D) -> Result<(), Error>
+    where
+        D: DatabaseAccess,
+    {
+        let _: Vec<serde_json::Value> = db.database()
+            .aql_bind_vars(r#"
+                FOR account IN Account
+                    FILTER account._key IN @keys AND account.remote != null
+                    UPDATE account WITH { remote: { last_refresh_attempted_at: @now } } IN Account
+            "#, hashmap! {
+                "keys" => json!(keys),
+                "now" => json!(Utc::now())
+            })
+            .await.map_err(aragog::Error::from)?;
+        Ok(())
+    }
+
+    /// Get the notes that this account has published that the viewer can see, newest first.
     pub async fn get_published_notes<D>(
         record: &Document<Account>,
-        page_limit: PageLimit,
+        viewer: Option<&Document<Account>>,
+        pagination: &Pagination,
         db: &D
-    ) -> Result<Vec<Document<Note>>, Error>
+    ) -> Result<Page<Document<Note>>, Error>
     where
         D: DatabaseAccess,
     {
-        Ok(Note::get(
-            &Query::outbound(1, 1, "Publish", record.id().as_str())
-                .apply_page_limit(page_limit)
-                .sort("created_at", Some(SortDirection::Desc))
-                .with_collections(&["Account", "Publish", "Note"]),
-            db
-        ).await?.wrap())
+        let page = pagination.to_aql("note.created_at", "note._key");
+
+        let mut vars = pagination.bind_vars();
+        vars.extend(viewer_bind_vars(viewer));
+        vars.insert("account_id", json!(record.id()));
+
+        let res: Vec<CursorItem<Document<Note>>> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Account, Publish, Note, Follow
+                FOR note IN 1..1 OUTBOUND @account_id Publish
+                    FILTER IS_SAME_COLLECTION("Note", note)
+                    FILTER {can_view}
+                    {page}
+                    RETURN {{ cursor: {{ created_at: note.created_at, key: note._key }}, item: note }}
+            "#, can_view = can_view_aql("note")), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(Page::from_rows(pagination, res))
     }
 
-    /// Count the number of notes that this account has published.
+    /// Count the number of notes that this account has published that the viewer can see.
     pub async fn count_published_notes<D>(
         record: &Document<Account>,
+        viewer: Option<&Document<Account>>,
         db: &D
     ) -> Result<u64, Error>
     where
         D: DatabaseAccess,
     {
+        let mut vars = viewer_bind_vars(viewer);
+        vars.insert("account_id", json!(record.id()));
+
         let mut res: Vec<u64> = db.database()
-            .aql_bind_vars(r#"
-                WITH Publish
-                FOR edge in Publish
-                    FILTER edge._from == @account_id
-                        AND is_same_collection("Note", edge._to)
+            .aql_bind_vars(&format!(r#"
+                WITH Account, Publish, Note, Follow
+                FOR note IN 1..1 OUTBOUND @account_id Publish
+                    FILTER IS_SAME_COLLECTION("Note", note)
+                    FILTER {can_view}
                     COLLECT WITH COUNT INTO length
                     RETURN length
-            "#, hashmap! {
-                "account_id" => json!(record.id())
-            })
+            "#, can_view = can_view_aql("note")), vars)
             .await.map_err(aragog::Error::from)?;
         Ok(res.pop().unwrap_or(0))
     }
 
-    /// Get the list of accounts that this account is following.
+    /// Get the list of accounts that this account is following, most recently followed first.
     pub async fn get_following<D>(
         record: &Document<Account>,
-        page_limit: PageLimit,
+        pagination: &Pagination,
         db: &D
-    ) -> Result<Vec<Document<Account>>, Error>
+    ) -> Result<Page<Document<Account>>, Error>
     where
         D: DatabaseAccess,
     {
-        let limit = page_limit.to_aql();
-        Ok(db.database()
+        let page = pagination.to_aql("edge.created_at", "edge._key");
+
+        let mut vars = pagination.bind_vars();
+        vars.insert("account_id", json!(record.id()));
+
+        let res: Vec<CursorItem<Document<Account>>> = db.database()
             .aql_bind_vars(&format!(r#"
                 WITH Follow, Account
                 FOR edge in Follow
                     FILTER edge._from == @account_id
                        AND edge.accepted == true
+                    {page}
                     FOR doc in Account
                         FILTER doc._id == edge._to
-                        {limit}
-                        RETURN doc
-            "#), hashmap! {
-                "account_id" => json!(record.id())
-            })
-            .await.map_err(aragog::Error::from)?)
+                        RETURN {{ cursor: {{ created_at: edge.created_at, key: edge._key }}, item: doc }}
+            "#), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(Page::from_rows(pagination, res))
     }
 
     /// Count the number of accounts that this account is following.
@@ -230,27 +495,57 @@ impl Account {
         Ok(res.pop().unwrap_or(0))
     }
 
-    /// Get the list of accounts that are following this account.
+    /// Get the list of accounts that are following this account, most recent followers first.
     pub async fn get_followers<D>(
         record: &Document<Account>,
-        page_limit: PageLimit,
+        pagination: &Pagination,
         db: &D
-    ) -> Result<Vec<Document<Account>>, Error>
+    ) -> Result<Page<Document<Account>>, Error>
     where
         D: DatabaseAccess,
     {
-        let limit = page_limit.to_aql();
-        Ok(db.database()
+        let page = pagination.to_aql("edge.created_at", "edge._key");
+
+        let mut vars = pagination.bind_vars();
+        vars.insert("account_id", json!(record.id()));
+
+        let res: Vec<CursorItem<Document<Account>>> = db.database()
             .aql_bind_vars(&format!(r#"
                 WITH Follow, Account
                 FOR edge in Follow
                     FILTER edge._to == @account_id
                        AND edge.accepted == true
+                    {page}
+                    FOR doc in Account
+                        FILTER doc._id == edge._from
+                        RETURN {{ cursor: {{ created_at: edge.created_at, key: edge._key }}, item: doc }}
+            "#), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(Page::from_rows(pagination, res))
+    }
+
+    /// Get the inboxes of the remote accounts that are following this account. Shared inboxes are
+    /// used where available, so that each server only appears once.
+    pub async fn get_follower_inboxes<D>(
+        record: &Document<Account>,
+        db: &D
+    ) -> Result<Vec<Url>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Ok(db.database()
+            .aql_bind_vars(r#"
+                WITH Follow, Account
+                FOR edge in Follow
+                    FILTER edge._to == @account_id
+                       AND edge.accepted == true
+                       AND edge.from_remote == true
                     FOR doc in Account
                         FILTER doc._id == edge._from
-                        {limit}
-                        RETURN doc
-            "#), hashmap! {
+                        LET inbox = NOT_NULL(doc.remote.shared_inbox, doc.remote.inbox)
+                        FILTER inbox != null
+                        RETURN DISTINCT inbox
+            "#, hashmap! {
                 "account_id" => json!(record.id())
             })
             .await.map_err(aragog::Error::from)?)
@@ -279,36 +574,88 @@ impl Account {
         Ok(res.pop().unwrap_or(0))
     }
 
-    /// Get the latest posts that this account's followers have published or announced.
+    /// Get the latest posts that this account and the accounts it follows have published or
+    /// shared.
     pub async fn get_timeline<D>(
         record: &Document<Account>,
-        page_limit: PageLimit,
+        pagination: &Pagination,
         db: &D
     ) -> Result<Vec<Document<Note>>, Error> 
     where
         D: DatabaseAccess,
     {
+        Ok(Account::get_home_timeline(record, TimelineOptions::default(), pagination, db).await?
+            .items
+            .into_iter()
+            .map(|entry| entry.note)
+            .collect())
+    }
+
+    /// Get the home timeline of this account: the latest posts that it and the accounts it
+    /// follows have published or shared, with their authors. Only posts that this account can see
+    /// are included.
+    pub async fn get_home_timeline<D>(
+        record: &Document<Account>,
+        options: TimelineOptions,
+        pagination: &Pagination,
+        db: &D
+    ) -> Result<Page<TimelineEntry>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let page = pagination.to_aql("edge.created_at", "edge._key");
+
+        let mut vars = pagination.bind_vars();
+        vars.extend(viewer_bind_vars(Some(record)));
+        vars.insert("account_id", json!(record.id()));
+        vars.insert("exclude_replies", json!(options.exclude_replies));
+        vars.insert("exclude_shares", json!(options.exclude_shares));
+
         // Really impossible to do this efficiently without a raw query
-        let res: Vec<Document<Note>> = db.database()
-            .aql_bind_vars(r#"
+        let res: Vec<CursorItem<TimelineEntry>> = db.database()
+            .aql_bind_vars(&format!(r#"
                 WITH Account, Follow, Publish, Share, Note
-                FOR account IN Account
-                    FILTER account._key == @account_key
-                    FOR note, edge, path IN 2..2 OUTBOUND account Follow, Publish, Share
-                        FILTER path.edges[0].accepted == true
-                        SORT path.edges[1].created_at DESC
-                        LIMIT @offset, @limit
-                        RETURN note
-            "#, hashmap! {
-                "account_key" => json!(record.key().as_str()),
-                "offset" => json!(page_limit.offset()),
-                "limit" => json!(page_limit.limit)
-            })
+                LET sources = APPEND([@account_id], (
+                    FOR follow IN Follow
+                        FILTER follow._from == @account_id
+                           AND follow.accepted == true
+                        RETURN follow._to
+                ), true)
+                LET publishes = (
+                    FOR edge IN Publish
+                        FILTER edge._from IN sources
+                           AND IS_SAME_COLLECTION("Note", edge._to)
+                        RETURN edge
+                )
+                LET shares = @exclude_shares ? [] : (
+                    FOR edge IN Share
+                        FILTER edge._from IN sources
+                        RETURN edge
+                )
+                FOR edge IN UNION(publishes, shares)
+                    LET note = DOCUMENT(edge._to)
+                    FILTER note != null
+                    FILTER !@exclude_replies OR note.in_reply_to == null
+                    FILTER {can_view}
+                    {page}
+                    LET shared = IS_SAME_COLLECTION("Share", edge)
+                    RETURN {{
+                        cursor: {{ created_at: edge.created_at, key: edge._key }},
+                        item: {{
+                            note,
+                            author: DOCUMENT("Account", note.from),
+                            shared_by: shared ? DOCUMENT(edge._from) : null,
+                            created_at: edge.created_at
+                        }}
+                    }}
+            "#, can_view = can_view_aql("note")), vars)
             .await.map_err(aragog::Error::from)?;
-        Ok(res)
+        Ok(Page::from_rows(pagination, res))
     }
 
     fn before_create(&mut self) -> Result<(), aragog::Error> {
+        self.username_normalized = Some(self.username.to_lowercase());
+
         if self.is_local() {
             self.created_at = Some(Utc::now());
         }
@@ -316,6 +663,8 @@ impl Account {
     }
 
     fn before_save(&mut self) -> Result<(), aragog::Error> {
+        self.username_normalized = Some(self.username.to_lowercase());
+
         if self.is_local() {
             self.updated_at = Some(Utc::now());
         }
@@ -323,9 +672,14 @@ impl Account {
     }
 }
 
+/// The time before which an account fetch is stale, if it isn't too far back to represent.
+fn stale_cutoff(max_age: std::time::Duration) -> Option<DateTime<Utc>> {
+    Utc::now().checked_sub_signed(chrono::Duration::from_std(max_age).ok()?)
+}
+
 #[async_trait(?Send)]
 impl ToObject for Document<Account> {
-    type Output = Ext<Person, ApActorProperties>;
+    type Output = ActorObject;
     type Error = crate::activitystreams::Error;
 
     async fn to_object<U, E>(&self, urls: &U) -> Result<Self::Output, E>
@@ -343,17 +697,63 @@ impl ToObject for Document<Account> {
         let following_url = urls.url_for_account_following(self.key()).await?;
         let shared_inbox_url = urls.url_for_shared_inbox()?;
 
+        let public_key = match self.public_key_pem {
+            Some(ref public_key_pem) => Some(PublicKey {
+                id: urls.url_for_account_public_key(self.key()).await?,
+                owner: account_url.clone(),
+                public_key_pem: public_key_pem.clone(),
+            }),
+            None => None
+        };
+
+        let avatar = match self.profile.avatar {
+            Some(ref avatar) => avatar.url_for(urls)?.map(|url| AttachmentObject::new(avatar, url)),
+            None => None
+        };
+
+        let header = match self.profile.header {
+            Some(ref header) => header.url_for(urls)?.map(|url| AttachmentObject::new(header, url)),
+            None => None
+        };
+
         (|| {
             let o = &mut person.object_props;
 
             o.set_id(account_url)?;
             o.set_context_xsd_any_uri(activitystreams::context())?;
-            o.set_name_xsd_string(self.username.to_owned())?;
+            o.set_name_xsd_string(
+                self.profile.display_name.clone().unwrap_or_else(|| self.username.clone()))?;
+
+            if let Some(ref summary) = self.profile.summary {
+                o.set_summary_xsd_string(summary.clone())?;
+            }
+
+            if let Some(avatar) = avatar {
+                o.set_icon_base_box(avatar)?;
+            }
+
+            if let Some(header) = header {
+                o.set_image_base_box(header)?;
+            }
+
+            let mut fields: Vec<BaseBox> = vec![];
+
+            for field in &self.profile.fields {
+                fields.push(PropertyValue::new(field).try_into()?);
+            }
+
+            if !fields.is_empty() {
+                o.set_many_attachment_base_boxes(fields)?;
+            }
 
             if let Some(created_at) = self.created_at.clone() {
                 o.set_published(DateTime::<FixedOffset>::from(created_at))?;
             }
 
+            if let Some(updated_at) = self.updated_at.clone() {
+                o.set_updated(DateTime::<FixedOffset>::from(updated_at))?;
+            }
+
             actor_properties.set_preferred_username(self.username.clone())?;
             actor_properties.set_inbox(inbox_url)?;
             actor_properties.set_outbox(outbox_url)?;
@@ -367,35 +767,66 @@ impl ToObject for Document<Account> {
             Ok::<_, Self::Error>(())
         })()?;
 
-        Ok(Ext { base: person, extension: actor_properties })
+        Ok(Ext {
+            base: Ext { base: person, extension: actor_properties },
+            extension: PublicKeyExtension { public_key },
+        })
     }
 }
 
-impl TryFrom<Ext<Person, ApActorProperties>> for Account {
+impl TryFrom<ActorObject> for Account {
     type Error = crate::error::Error;
 
-    fn try_from(person: Ext<Person, ApActorProperties>) -> Result<Self, Self::Error> {
+    /// Convert a remote actor. The summary and profile field values are sanitized.
+    fn try_from(actor: ActorObject) -> Result<Self, Self::Error> {
         let missing = |s: &'static str| Error::ConversionMissingField(s.into());
-        let id = person.base.object_props.get_id().ok_or(missing("id"))?;
+        let Ext { base: person, extension: PublicKeyExtension { public_key } } = actor;
+        let o = &person.base.object_props;
+        let id = o.get_id().ok_or(missing("id"))?;
+
+        let json = serde_json::to_value(&person)?;
+
+        // A key owned by someone else can't be used to verify this account's signatures
+        let public_key = public_key
+            .filter(|key| key.owner == *id.as_url())
+            .map(|key| RemotePublicKey { id: key.id, pem: key.public_key_pem });
 
         Ok(Account {
             username: person.extension.get_preferred_username()
                 .ok_or(missing("preferred_username"))?.clone().into_string(),
+            username_normalized: None,
             // This is just a best guess at the domain. It can be refined by a webfinger.
             domain: id.as_url().host_str().map(|s| s.to_owned()),
             remote: Some(RemoteAccountInfo {
                 uri: id.as_url().clone(),
                 // we don't know, whoever is consuming this should set it.
                 last_fetched_at: None,
+                last_refresh_attempted_at: None,
                 inbox: Some(person.extension.get_inbox().as_url().clone()),
+                shared_inbox: person.extension.get_endpoints()
+                    .and_then(|endpoints| endpoints.shared_inbox.as_ref())
+                    .map(|u| u.as_url().clone()),
                 outbox: Some(person.extension.get_outbox().as_url().clone()),
                 followers: person.extension.get_followers().map(|u| u.as_url().clone()),
                 following: person.extension.get_following().map(|u| u.as_url().clone()),
+                public_key,
             }),
-            created_at: person.base.object_props.get_published()
-                .map(|d| d.as_datetime().clone().into()),
-            updated_at: person.base.object_props.get_updated()
-                .map(|d| d.as_datetime().clone().into()),
+            public_key_pem: None,
+            profile: Profile {
+                display_name: o.get_name_xsd_string()
+                    .map(|name| name.clone().into_string())
+                    .filter(|name| !name.is_empty()),
+                summary: o.get_summary_xsd_string()
+                    .map(|summary| sanitize_html(&summary.clone().into_string())),
+                avatar: get_image(&json, "icon"),
+                header: get_image(&json, "image"),
+                fields: get_property_values(&json).into_iter()
+                    .map(|field| ProfileField { value: sanitize_html(&field.value), ..field })
+                    .collect(),
+                source: None,
+            },
+            created_at: o.get_published().map(|d| d.as_datetime().clone().into()),
+            updated_at: o.get_updated().map(|d| d.as_datetime().clone().into()),
         })
     }
 }
@@ -405,10 +836,19 @@ impl RemoteAccountInfo {
         RemoteAccountInfo {
             uri,
             last_fetched_at: None,
+            last_refresh_attempted_at: None,
             inbox: None,
+            shared_inbox: None,
             outbox: None,
             followers: None,
             following: None,
+            public_key: None,
         }
     }
+
+    /// The inbox that activities for this user should be delivered to, which is the shared inbox
+    /// if the server has one.
+    pub fn delivery_inbox(&self) -> Option<&Url> {
+        self.shared_inbox.as_ref().or(self.inbox.as_ref())
+    }
 }
@@ -0,0 +1,144 @@
+use aragog::{compare, DatabaseAccess, Record};
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::password_hash::rand_core::OsRng;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{Document, Error, Wrap};
+
+/// Minimum length of a password, in characters.
+pub const PASSWORD_MIN_LENGTH: usize = 8;
+
+/// A hash made with the same parameters as real ones, that no account's password comes from.
+const DUMMY_PASSWORD_HASH: &str = concat!(
+    "$argon2id$v=19$m=4096,t=3,p=1$dmVydGl4LWR1bW15LXNhbHQ$",
+    "CAcw/W0adzLIDNHHDqzmbnb5wpHt90iujGxBnfdfWFM",
+);
+
+/// The password a local account logs in with.
+///
+/// Like [AccountKey](crate::AccountKey), this is kept out of [Account](crate::Account) so that it
+/// is never sent anywhere along with the account.
+#[derive(Clone, Serialize, Deserialize, Record)]
+#[before_create(func = "before_create")]
+#[before_save(func = "before_save")]
+pub struct AccountCredential {
+    /// Account key
+    pub account: String,
+
+    /// Argon2 hash of the password, in PHC string format.
+    pub password_hash: String,
+
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl AccountCredential {
+    /// Hash a password for an account.
+    pub fn new(account_key: &str, password: &str) -> Result<AccountCredential, Error> {
+        Ok(AccountCredential {
+            account: account_key.to_owned(),
+            password_hash: AccountCredential::hash_password(password)?,
+            created_at: None,
+            updated_at: None,
+        })
+    }
+
+    /// Find the credentials belonging to an account.
+    pub async fn find_by_account<D>(
+        account_key: &str,
+        db: &D
+    ) -> Result<Document<AccountCredential>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        AccountCredential::get(
+            &AccountCredential::query()
+                .bind_var("account", account_key)
+                .filter(compare!(field "account").equals("@account").into()),
+            db,
+        )
+        .await?
+        .first_record()
+        .wrap()
+        .ok_or_else(|| Error::NotFound {
+            model: "AccountCredential".into(),
+            params: json!({"account": account_key})
+        })
+    }
+
+    /// Set the password of an account, replacing the existing one if there is one.
+    pub async fn set_password<D>(
+        account_key: &str,
+        password: &str,
+        db: &D
+    ) -> Result<Document<AccountCredential>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        match AccountCredential::find_by_account(account_key, db).await {
+            Ok(mut credential) => {
+                credential.password_hash = AccountCredential::hash_password(password)?;
+                credential.save(db).await?;
+                Ok(credential)
+            },
+            Err(e) if e.is_not_found() => {
+                let credential = AccountCredential::new(account_key, password)?;
+                Ok(AccountCredential::create(credential, db).await?.wrap())
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check that a password is acceptable, and hash it for storage.
+    pub fn hash_password(password: &str) -> Result<String, Error> {
+        if password.chars().count() < PASSWORD_MIN_LENGTH {
+            return Err(Error::Validation(
+                format!("Password must be at least {PASSWORD_MIN_LENGTH} characters long").into()));
+        }
+
+        let salt = SaltString::generate(&mut OsRng);
+
+        Ok(Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| Error::Credentials(e.to_string().into()))?
+            .to_string())
+    }
+
+    /// Check a password against the stored hash.
+    pub fn verify_password(&self, password: &str) -> bool {
+        match PasswordHash::new(&self.password_hash) {
+            Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+            Err(e) => {
+                log::warn!("Invalid password hash for account {}: {}", self.account, e);
+                false
+            }
+        }
+    }
+
+    /// Check a password when there's no credential to check it against, which always fails.
+    ///
+    /// This takes as long as [verify_password](AccountCredential::verify_password), so that a
+    /// missing account can't be told apart from a wrong password by how long it takes.
+    pub fn verify_dummy_password(password: &str) -> bool {
+        if let Ok(hash) = PasswordHash::new(DUMMY_PASSWORD_HASH) {
+            let _ = Argon2::default().verify_password(password.as_bytes(), &hash);
+        }
+        false
+    }
+
+    fn before_create(&mut self) -> Result<(), aragog::Error> {
+        self.created_at = Some(Utc::now());
+        Ok(())
+    }
+
+    fn before_save(&mut self) -> Result<(), aragog::Error> {
+        self.updated_at = Some(Utc::now());
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use aragog::{compare, DatabaseAccess, Record};
+use chrono::{DateTime, Utc};
+use rsa::RsaPrivateKey;
+use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{Account, Document, Error, Wrap};
+
+/// Size of generated RSA keys, in bits.
+pub const ACCOUNT_KEY_BITS: usize = 2048;
+
+/// The keypair a local account uses to sign its requests to remote servers.
+///
+/// This is stored in its own collection rather than on [Account], so that the private key is never
+/// sent anywhere along with the account.
+#[derive(Clone, Serialize, Deserialize, Record)]
+#[before_create(func = "before_create")]
+pub struct AccountKey {
+    /// Account key
+    pub account: String,
+
+    /// PEM encoded (SPKI) public key.
+    pub public_key_pem: String,
+
+    /// PEM encoded (PKCS#8) private key.
+    pub private_key_pem: String,
+
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl AccountKey {
+    /// Generate a new keypair for an account. This is slow, as it has to find primes, so it's done
+    /// on a blocking thread instead of holding up the executor.
+    pub async fn generate(account_key: &str) -> Result<AccountKey, Error> {
+        let account_key = account_key.to_owned();
+
+        actix_rt::task::spawn_blocking(move || AccountKey::generate_blocking(account_key)).await
+            .map_err(|e| Error::KeyGeneration(e.to_string().into()))?
+    }
+
+    fn generate_blocking(account_key: String) -> Result<AccountKey, Error> {
+        let key_error = |e: &dyn std::fmt::Display| Error::KeyGeneration(e.to_string().into());
+
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), ACCOUNT_KEY_BITS)
+            .map_err(|e| key_error(&e))?;
+
+        let private_key_pem = private_key.to_pkcs8_pem(LineEnding::LF)
+            .map_err(|e| key_error(&e))?
+            .to_string();
+
+        let public_key_pem = private_key.to_public_key().to_public_key_pem(LineEnding::LF)
+            .map_err(|e| key_error(&e))?;
+
+        Ok(AccountKey {
+            account: account_key,
+            public_key_pem,
+            private_key_pem,
+            created_at: None,
+        })
+    }
+
+    /// Find the keypair belonging to an account.
+    pub async fn find_by_account<D>(account_key: &str, db: &D) -> Result<Document<AccountKey>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        AccountKey::get(
+            &AccountKey::query()
+                .bind_var("account", account_key)
+                .filter(compare!(field "account").equals("@account").into()),
+            db,
+        )
+        .await?
+        .first_record()
+        .wrap()
+        .ok_or_else(|| Error::NotFound {
+            model: "AccountKey".into(),
+            params: json!({"account": account_key})
+        })
+    }
+
+    /// Find the keypair belonging to a local account, generating and storing one if it doesn't
+    /// have one yet. This is for accounts created before keypairs were generated along with them
+    /// (see [Account::create_local]), and it also stores the new public key on the account.
+    pub async fn find_or_create<D>(
+        account: &Document<Account>,
+        db: &D
+    ) -> Result<Document<AccountKey>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        match AccountKey::find_by_account(account.key(), db).await {
+            Err(e) if e.is_not_found() && account.is_local() => {
+                log::info!("Generating keypair for account {}", account.key());
+                let account_key =
+                    AccountKey::create(AccountKey::generate(account.key()).await?, db).await?;
+
+                let mut account = Account::find(account.key(), db).await?;
+                account.public_key_pem = Some(account_key.public_key_pem.clone());
+                account.save(db).await?;
+
+                Ok(account_key.wrap())
+            },
+            other => other,
+        }
+    }
+
+    fn before_create(&mut self) -> Result<(), aragog::Error> {
+        self.created_at = Some(Utc::now());
+        Ok(())
+    }
+}
@@ -1,13 +1,18 @@
 use std::{convert::Infallible, io};
 
 use activitystreams::collection::{OrderedCollection, OrderedCollectionPage};
+use activitystreams::actor::{Actor, Person, properties::ApActorProperties};
+use activitystreams::ext::{Ext, Extension};
+use activitystreams::object::Tombstone;
 use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Serialize, Deserialize};
 use url::Url;
 use activitystreams::{primitives::*, BaseBox};
 use activitystreams::object::properties::ObjectProperties;
 use activitystreams::activity::properties::ActorAndObjectProperties;
 
-use crate::PageLimit;
+use crate::{Attachment, Pagination, ProfileField};
 
 /// Resolves URLs to be used for various links within ActivityStreams documents
 #[async_trait]
@@ -17,13 +22,20 @@ pub trait UrlFor {
     async fn url_for_account(&self, key: &str) -> Result<Url, Self::Error>;
     async fn url_for_account_inbox(&self, key: &str) -> Result<Url, Self::Error>;
     async fn url_for_account_outbox(&self, key: &str) -> Result<Url, Self::Error>;
-    async fn url_for_account_outbox_page(&self, key: &str, page: u32) -> Result<Url, Self::Error>;
+    async fn url_for_account_outbox_page(&self, key: &str, page: &Pagination) -> Result<Url, Self::Error>;
     async fn url_for_account_followers(&self, key: &str) -> Result<Url, Self::Error>;
-    async fn url_for_account_followers_page(&self, key: &str, page: u32) -> Result<Url, Self::Error>;
+    async fn url_for_account_followers_page(&self, key: &str, page: &Pagination) -> Result<Url, Self::Error>;
     async fn url_for_account_following(&self, key: &str) -> Result<Url, Self::Error>;
-    async fn url_for_account_following_page(&self, key: &str, page: u32) -> Result<Url, Self::Error>;
+    async fn url_for_account_following_page(&self, key: &str, page: &Pagination) -> Result<Url, Self::Error>;
+    async fn url_for_account_public_key(&self, key: &str) -> Result<Url, Self::Error>;
 
     async fn url_for_note(&self, key: &str) -> Result<Url, Self::Error>;
+    async fn url_for_note_replies(&self, key: &str) -> Result<Url, Self::Error>;
+    async fn url_for_note_replies_page(&self, key: &str, page: &Pagination) -> Result<Url, Self::Error>;
+
+    fn url_for_hashtag(&self, name: &str) -> Result<Url, Self::Error>;
+
+    fn url_for_media_attachment(&self, key: &str) -> Result<Url, Self::Error>;
 
     fn url_for_shared_inbox(&self) -> Result<Url, Self::Error>;
 }
@@ -40,6 +52,110 @@ pub trait ToObject {
         E: From<Self::Error> + From<U::Error>;
 }
 
+/// The `publicKey` of an actor, used to verify the HTTP signatures it makes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKey {
+    pub id: Url,
+    pub owner: Url,
+    pub public_key_pem: String,
+}
+
+/// A `Hashtag` tag. This isn't part of the ActivityStreams vocabulary, but it's what everyone
+/// uses to tag objects with hashtags.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashtagLink {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub href: Url,
+    pub name: String,
+}
+
+impl HashtagLink {
+    pub fn new(href: Url, name: String) -> HashtagLink {
+        HashtagLink { kind: "Hashtag".into(), href, name }
+    }
+}
+
+impl activitystreams::Base for HashtagLink {}
+
+/// A file attached to an object, as found in `attachment`. `blurhash` isn't part of the
+/// ActivityStreams vocabulary either, but it's widely understood.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentObject {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: Url,
+    pub media_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blurhash: Option<String>,
+}
+
+impl AttachmentObject {
+    pub fn new(attachment: &Attachment, url: Url) -> AttachmentObject {
+        AttachmentObject {
+            kind: attachment.kind().into(),
+            url,
+            media_type: attachment.media_type.clone(),
+            name: attachment.description.clone(),
+            blurhash: attachment.blurhash.clone(),
+        }
+    }
+}
+
+impl activitystreams::Base for AttachmentObject {}
+
+/// A `PropertyValue`, which is how profile fields are given in an actor's `attachment`. This comes
+/// from schema.org rather than ActivityStreams.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropertyValue {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub name: String,
+    pub value: String,
+}
+
+impl PropertyValue {
+    pub fn new(field: &ProfileField) -> PropertyValue {
+        PropertyValue {
+            kind: "PropertyValue".into(),
+            name: field.name.clone(),
+            value: field.value.clone(),
+        }
+    }
+}
+
+impl activitystreams::Base for PropertyValue {}
+
+/// Extension adding `publicKey` to an actor.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyExtension {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<PublicKey>,
+}
+
+impl<T> Extension<T> for PublicKeyExtension where T: Actor {}
+
+/// The full ActivityStreams representation of an actor, as served and fetched by us.
+pub type ActorObject = Ext<Ext<Person, ApActorProperties>, PublicKeyExtension>;
+
+/// Extension adding a link to the `replies` collection of an object.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepliesExtension {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replies: Option<Url>,
+}
+
+impl<T> Extension<T> for RepliesExtension where T: activitystreams::object::Object {}
+
+/// The representation of a local note served at its own url.
+pub type NoteObject = Ext<activitystreams::object::Note, RepliesExtension>;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("{0}")]
@@ -58,6 +174,8 @@ pub enum Error {
     XsdNonNegativeInteger(#[from] XsdNonNegativeIntegerError),
     #[error("{0}")]
     Io(#[from] io::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
     #[error("missing actor from attributed_at")]
     MissingActor,
 }
@@ -68,6 +186,133 @@ impl From<Infallible> for Error {
     }
 }
 
+/// Get the ids in a property of a JSON object. The property may hold a single value or an array,
+/// and each value may be either a URI or an embedded object with an `id`.
+pub fn get_ids(object: &serde_json::Value, property: &str) -> Vec<Url> {
+    let id_of = |value: &serde_json::Value| match value {
+        serde_json::Value::String(uri) => uri.parse().ok(),
+        serde_json::Value::Object(map) => map.get("id")?.as_str()?.parse().ok(),
+        _ => None
+    };
+
+    match object.get(property) {
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(id_of).collect(),
+        Some(value) => id_of(value).into_iter().collect(),
+        None => vec![]
+    }
+}
+
+/// Get the `href` and `name` of each `Mention` in the `tag` property of a JSON object.
+pub fn get_mention_tags(object: &serde_json::Value) -> Vec<(Url, Option<String>)> {
+    let mention_of = |value: &serde_json::Value| {
+        if value.get("type")?.as_str()? != "Mention" {
+            return None;
+        }
+
+        let href = value.get("href")?.as_str()?.parse().ok()?;
+        let name = value.get("name").and_then(|name| name.as_str()).map(|name| name.to_owned());
+
+        Some((href, name))
+    };
+
+    match object.get("tag") {
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(mention_of).collect(),
+        Some(value) => mention_of(value).into_iter().collect(),
+        None => vec![]
+    }
+}
+
+/// Get the names of each `Hashtag` in the `tag` property of a JSON object, as they're given.
+pub fn get_hashtag_tags(object: &serde_json::Value) -> Vec<String> {
+    let hashtag_of = |value: &serde_json::Value| {
+        if value.get("type")?.as_str()? != "Hashtag" {
+            return None;
+        }
+
+        Some(value.get("name")?.as_str()?.to_owned())
+    };
+
+    match object.get("tag") {
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(hashtag_of).collect(),
+        Some(value) => hashtag_of(value).into_iter().collect(),
+        None => vec![]
+    }
+}
+
+/// Get the files in the `attachment` property of a JSON object, as remote attachments. Links
+/// without a url are skipped, and a missing `mediaType` is taken to be `application/octet-stream`.
+pub fn get_attachments(object: &serde_json::Value) -> Vec<Attachment> {
+    match object.get("attachment") {
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(attachment_of).collect(),
+        Some(value) => attachment_of(value).into_iter().collect(),
+        None => vec![]
+    }
+}
+
+/// Get an image property of a JSON object, such as `icon`, as a remote attachment. If there are
+/// several, the first is used.
+pub fn get_image(object: &serde_json::Value, property: &str) -> Option<Attachment> {
+    match object.get(property)? {
+        serde_json::Value::Array(values) => attachment_of(values.first()?),
+        value => attachment_of(value),
+    }
+}
+
+/// Get the `PropertyValue`s in the `attachment` property of a JSON object, which is how profile
+/// fields are given. Values are as given, so they still need to be sanitized.
+pub fn get_property_values(object: &serde_json::Value) -> Vec<ProfileField> {
+    let field_of = |value: &serde_json::Value| {
+        if value.get("type")?.as_str()? != "PropertyValue" {
+            return None;
+        }
+
+        Some(ProfileField {
+            name: value.get("name")?.as_str()?.to_owned(),
+            value: value.get("value")?.as_str()?.to_owned(),
+        })
+    };
+
+    match object.get("attachment") {
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(field_of).collect(),
+        Some(value) => field_of(value).into_iter().collect(),
+        None => vec![]
+    }
+}
+
+/// Make a remote attachment from a JSON object with a `url`, or from a URI by itself.
+fn attachment_of(value: &serde_json::Value) -> Option<Attachment> {
+    // `url` may be a URI or a Link, or several of them, in which case the first is used
+    let url_of = |value: &serde_json::Value| -> Option<Url> {
+        let value = match value {
+            serde_json::Value::Array(values) => values.first()?,
+            value => value
+        };
+
+        match value {
+            serde_json::Value::String(url) => url.parse().ok(),
+            serde_json::Value::Object(map) => map.get("href")?.as_str()?.parse().ok(),
+            _ => None
+        }
+    };
+
+    let string = |property: &str| {
+        value.get(property).and_then(|s| s.as_str()).map(|s| s.to_owned())
+    };
+
+    let remote_url = match value {
+        serde_json::Value::String(_) => url_of(value)?,
+        _ => url_of(value.get("url")?)?,
+    };
+
+    Some(Attachment {
+        media_attachment: None,
+        remote_url: Some(remote_url),
+        media_type: string("mediaType").unwrap_or_else(|| "application/octet-stream".into()),
+        blurhash: string("blurhash"),
+        description: string("name"),
+    })
+}
+
 pub fn make_actor_and_object_activity<A, O>(object: O) -> Result<A, Error>
 where
     A: Default + AsMut<ActorAndObjectProperties>,
@@ -84,6 +329,25 @@ where
     Ok(activity)
 }
 
+/// Make a Tombstone to stand in for a deleted object.
+pub fn make_tombstone(
+    id: Url,
+    former_type: &str,
+    deleted: Option<DateTime<Utc>>,
+) -> Result<Tombstone, Error> {
+    let mut tombstone = Tombstone::new();
+
+    tombstone.object_props.set_context_xsd_any_uri(activitystreams::context())?;
+    tombstone.object_props.set_id(id)?;
+    tombstone.tombstone_props.set_former_type_xsd_string(former_type)?;
+
+    if let Some(deleted) = deleted {
+        tombstone.tombstone_props.set_deleted(DateTime::<FixedOffset>::from(deleted))?;
+    }
+
+    Ok(tombstone)
+}
+
 pub fn make_ordered_collection(
     self_url: Url,
     first_page_url: Url,
@@ -100,9 +364,9 @@ pub fn make_ordered_collection(
 }
 
 pub fn make_ordered_collection_page<T>(
-    page_limit: Option<PageLimit>,
     collection_url: Url,
     next_page_url: Option<Url>,
+    prev_page_url: Option<Url>,
     items: Vec<T>,
 ) -> Result<OrderedCollectionPage, Error>
 where
@@ -115,14 +379,12 @@ where
 
     col_page.collection_page_props.set_part_of_xsd_any_uri(collection_url)?;
 
-    match (page_limit, next_page_url) {
-        (Some(page_limit), _) if page_limit.limit as usize > items.len() => {
-            // Assume no next page
-        },
-        (_, Some(next_page_url)) => {
-            col_page.collection_page_props.set_next_xsd_any_uri(next_page_url)?;
-        },
-        _ => ()
+    if let Some(next_page_url) = next_page_url {
+        col_page.collection_page_props.set_next_xsd_any_uri(next_page_url)?;
+    }
+
+    if let Some(prev_page_url) = prev_page_url {
+        col_page.collection_page_props.set_prev_xsd_any_uri(prev_page_url)?;
     }
 
     col_page.collection_props.set_many_items_base_boxes(items)?;
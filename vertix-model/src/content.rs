@@ -0,0 +1,52 @@
+use ammonia::{Builder, UrlRelative};
+use serde::{Deserialize, Serialize};
+
+/// The format of what the author of a local note wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentType {
+    #[serde(rename = "text/plain")]
+    PlainText,
+    #[serde(rename = "text/markdown")]
+    Markdown,
+}
+
+impl Default for ContentType {
+    fn default() -> Self {
+        ContentType::PlainText
+    }
+}
+
+/// What the author of a local note wrote, before it was rendered to HTML for `content`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteSource {
+    pub content: String,
+
+    #[serde(default)]
+    pub media_type: ContentType,
+}
+
+impl NoteSource {
+    pub fn new(content: String, media_type: ContentType) -> NoteSource {
+        NoteSource { content, media_type }
+    }
+}
+
+/// Clean HTML so that only formatting and links remain. Everything else, including scripts,
+/// styles, images and event handlers, is removed. Links can only go to absolute http(s) or mailto
+/// urls, and are marked `nofollow`.
+pub fn sanitize_html(html: &str) -> String {
+    Builder::empty()
+        .add_tags(&[
+            "p", "br", "a", "span", "strong", "b", "em", "i", "u", "s", "del", "code", "pre",
+            "blockquote", "ul", "ol", "li", "h1", "h2", "h3", "h4", "h5", "h6",
+        ])
+        .add_clean_content_tags(&["script", "style"])
+        .add_tag_attributes("a", &["href", "class"])
+        .add_tag_attributes("span", &["class"])
+        .add_tag_attributes("ol", &["start"])
+        .add_url_schemes(&["http", "https", "mailto"])
+        .url_relative(UrlRelative::Deny)
+        .link_rel(Some("nofollow noopener noreferrer"))
+        .clean(html)
+        .to_string()
+}
@@ -2,10 +2,10 @@ use activitystreams::activity;
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 use aragog::{Record, EdgeRecord, DatabaseRecord, DatabaseAccess, compare};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Utc, FixedOffset};
 use serde_json::json;
 use url::Url;
-use crate::{Account, Error, Edge, Wrap, activitystreams::{ToObject, UrlFor}};
+use crate::{Account, Note, Error, Edge, Wrap, activitystreams::{ToObject, UrlFor}};
 
 macro_rules! created_at_hook {
     () => {
@@ -81,6 +81,22 @@ impl Follow {
         })?)
     }
 
+    /// Find follow by the uri the remote provided for it.
+    pub async fn find_by_uri<D>(uri: &Url, db: &D) -> Result<Edge<Follow>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Ok(EdgeRecord::get(
+            &Follow::query()
+                .bind_var("uri", uri.as_str())
+                .filter(compare!(field "uri").equals("@uri").into()),
+            db
+        ).await?.first_record().wrap().ok_or_else(|| Error::NotFound {
+            model: "Follow".into(),
+            params: json!({"uri": uri})
+        })?)
+    }
+
     // Find pending follows from an account.
     pub async fn find_pending_from<D>(from: &DatabaseRecord<Account>, db: &D)
         -> Result<Vec<Edge<Follow>>, Error>
@@ -162,20 +178,236 @@ impl Publish {
 #[before_create(func = "before_create")]
 #[serde(default)]
 pub struct Share {
+    /// Set if the remote provided a uri.
+    pub uri: Option<Url>,
+
     pub created_at: Option<DateTime<Utc>>,
 }
 
 impl Share {
+    /// Share (boost) a note from an account.
+    pub async fn link<D>(
+        actor: &DatabaseRecord<Account>,
+        note: &DatabaseRecord<Note>,
+        uri: Option<Url>,
+        db: &D
+    ) -> Result<Edge<Share>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let share = Share { uri, ..Share::default() };
+
+        Ok(DatabaseRecord::link(actor, note, db, share).await?.wrap())
+    }
+
+    /// Find share of a note by an account.
+    pub async fn find_between<D>(
+        actor: &DatabaseRecord<Account>,
+        note: &DatabaseRecord<Note>,
+        db: &D
+    ) -> Result<Edge<Share>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Ok(EdgeRecord::get(
+            &Share::query()
+                .bind_var("from", actor.id().as_str())
+                .bind_var("to", note.id().as_str())
+                .filter(
+                    compare!(field "_from").equals("@from")
+                        .and(compare!(field "_to").equals("@to")).into()),
+            db
+        ).await?.first_record().wrap().ok_or_else(|| Error::NotFound {
+            model: "Share".into(),
+            params: json!({"_from": actor.id(), "_to": note.id()})
+        })?)
+    }
+
+    /// Find share by the uri the remote provided for it.
+    pub async fn find_by_uri<D>(uri: &Url, db: &D) -> Result<Edge<Share>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Ok(EdgeRecord::get(
+            &Share::query()
+                .bind_var("uri", uri.as_str())
+                .filter(compare!(field "uri").equals("@uri").into()),
+            db
+        ).await?.first_record().wrap().ok_or_else(|| Error::NotFound {
+            model: "Share".into(),
+            params: json!({"uri": uri})
+        })?)
+    }
+
     created_at_hook!();
 }
 
+#[async_trait(?Send)]
+impl ToObject for Edge<Share> {
+    type Output = activity::Announce;
+    type Error = crate::activitystreams::Error;
+
+    async fn to_object<U, E>(&self, urls: &U) -> Result<Self::Output, E>
+    where
+        U: UrlFor,
+        E: From<Self::Error> + From<U::Error>
+    {
+        let (from_url, followers_url, note_url) = futures::try_join!(
+            urls.url_for_account(self.key_from()),
+            urls.url_for_account_followers(self.key_from()),
+            urls.url_for_note(self.key_to()),
+        )?;
+
+        let mut announce_activity = activity::Announce::new();
+
+        (|| {
+            let o = &mut announce_activity.object_props;
+
+            o.set_context_xsd_any_uri(activitystreams::context())?;
+            o.set_id(self.uri.clone().unwrap_or_else(|| {
+                let mut url = from_url.clone();
+                url.set_fragment(Some(&format!("shares/{}", self.key())));
+                url
+            }))?;
+
+            if let Some(created_at) = self.created_at.clone() {
+                o.set_published(DateTime::<FixedOffset>::from(created_at))?;
+            }
+
+            // Shares are always public
+            o.set_to_xsd_any_uri(activitystreams::public())?;
+            o.set_cc_xsd_any_uri(followers_url)?;
+
+            announce_activity.announce_props.set_actor_xsd_any_uri(from_url)?;
+            announce_activity.announce_props.set_object_xsd_any_uri(note_url)?;
+            Ok::<_, crate::activitystreams::Error>(())
+        })()?;
+
+        Ok(announce_activity)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default, Record)]
 #[before_create(func = "before_create")]
 #[serde(default)]
 pub struct Like {
+    /// Set if the remote provided a uri.
+    pub uri: Option<Url>,
+
     pub created_at: Option<DateTime<Utc>>,
 }
 
 impl Like {
+    /// Like a note from an account.
+    pub async fn link<D>(
+        actor: &DatabaseRecord<Account>,
+        note: &DatabaseRecord<Note>,
+        uri: Option<Url>,
+        db: &D
+    ) -> Result<Edge<Like>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let like = Like { uri, ..Like::default() };
+
+        Ok(DatabaseRecord::link(actor, note, db, like).await?.wrap())
+    }
+
+    /// Find like of a note by an account.
+    pub async fn find_between<D>(
+        actor: &DatabaseRecord<Account>,
+        note: &DatabaseRecord<Note>,
+        db: &D
+    ) -> Result<Edge<Like>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Ok(EdgeRecord::get(
+            &Like::query()
+                .bind_var("from", actor.id().as_str())
+                .bind_var("to", note.id().as_str())
+                .filter(
+                    compare!(field "_from").equals("@from")
+                        .and(compare!(field "_to").equals("@to")).into()),
+            db
+        ).await?.first_record().wrap().ok_or_else(|| Error::NotFound {
+            model: "Like".into(),
+            params: json!({"_from": actor.id(), "_to": note.id()})
+        })?)
+    }
+
+    /// Find like by the uri the remote provided for it.
+    pub async fn find_by_uri<D>(uri: &Url, db: &D) -> Result<Edge<Like>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Ok(EdgeRecord::get(
+            &Like::query()
+                .bind_var("uri", uri.as_str())
+                .filter(compare!(field "uri").equals("@uri").into()),
+            db
+        ).await?.first_record().wrap().ok_or_else(|| Error::NotFound {
+            model: "Like".into(),
+            params: json!({"uri": uri})
+        })?)
+    }
+
+    created_at_hook!();
+}
+
+#[async_trait(?Send)]
+impl ToObject for Edge<Like> {
+    type Output = activity::Like;
+    type Error = crate::activitystreams::Error;
+
+    async fn to_object<U, E>(&self, urls: &U) -> Result<Self::Output, E>
+    where
+        U: UrlFor,
+        E: From<Self::Error> + From<U::Error>
+    {
+        let (from_url, note_url) = futures::try_join!(
+            urls.url_for_account(self.key_from()),
+            urls.url_for_note(self.key_to()),
+        )?;
+
+        let mut like_activity = activity::Like::new();
+
+        (|| {
+            like_activity.object_props.set_context_xsd_any_uri(activitystreams::context())?;
+            like_activity.object_props.set_id(self.uri.clone().unwrap_or_else(|| {
+                let mut url = from_url.clone();
+                url.set_fragment(Some(&format!("likes/{}", self.key())));
+                url
+            }))?;
+            like_activity.like_props.set_actor_xsd_any_uri(from_url)?;
+            like_activity.like_props.set_object_xsd_any_uri(note_url)?;
+            Ok::<_, crate::activitystreams::Error>(())
+        })()?;
+
+        Ok(like_activity)
+    }
+}
+
+/// Edge from a note to the note it is a reply to.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Record)]
+#[before_create(func = "before_create")]
+#[serde(default)]
+pub struct Reply {
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl Reply {
+    created_at_hook!();
+}
+
+/// Edge from a note to a [Hashtag](crate::Hashtag) that it's tagged with.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Record)]
+#[before_create(func = "before_create")]
+#[serde(default)]
+pub struct Tagged {
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl Tagged {
     created_at_hook!();
 }
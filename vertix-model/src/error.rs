@@ -10,6 +10,18 @@ pub enum Error {
     #[error("ActivityStreams error: {0}")]
     ActivityStreams(#[source] crate::activitystreams::Error),
 
+    #[error("Key generation failed: {0}")]
+    KeyGeneration(Cow<'static, str>),
+
+    #[error("Credentials error: {0}")]
+    Credentials(Cow<'static, str>),
+
+    #[error("Validation failed: {0}")]
+    Validation(Cow<'static, str>),
+
+    #[error("Username is already taken: {0}")]
+    UsernameTaken(Cow<'static, str>),
+
     #[error("Conversion failed: missing field: {0}")]
     ConversionMissingField(Cow<'static, str>),
 
@@ -32,10 +44,20 @@ impl Error {
         }
     }
     
+    /// Whether the error is from a write that conflicted with a unique index.
+    pub fn is_conflict(&self) -> bool {
+        match self {
+            Error::Aragog(e) => e.http_code() == 409,
+            _ => false
+        }
+    }
+
     pub fn http_code(&self) -> u16 {
         match self {
             Error::Aragog(err) => err.http_code(),
             Error::NotFound { .. } => 404,
+            Error::Validation(_) => 400,
+            Error::UsernameTaken(_) => 409,
             _ => 500,
         }
     }
@@ -0,0 +1,149 @@
+use aragog::{compare, DatabaseAccess, Record};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    Error,
+    Document,
+    Wrap,
+    Pagination,
+    Page,
+    CursorItem,
+    Recipient,
+    TimelineEntry,
+    TimelineOptions,
+};
+
+/// A hashtag that notes can be tagged with, by [Tagged](crate::Tagged) edges.
+#[derive(Debug, Clone, Serialize, Deserialize, Record)]
+#[before_create(func = "before_create")]
+pub struct Hashtag {
+    /// Normalized name, without the `#`. See [Hashtag::normalize_name].
+    pub name: String,
+
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl Hashtag {
+    /// Normalize a hashtag name, with or without the `#`, so that different spellings match. Names
+    /// that aren't usable as hashtags give `None`.
+    pub fn normalize_name(name: &str) -> Option<String> {
+        let name = name.strip_prefix('#').unwrap_or(name);
+
+        let valid = name.chars().all(|c| c.is_alphanumeric() || c == '_') &&
+            name.chars().any(|c| c.is_alphabetic());
+
+        valid.then(|| name.to_lowercase())
+    }
+
+    /// Normalize a list of hashtag names, leaving out duplicates and unusable names.
+    pub fn normalize_names<'a>(names: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        let mut normalized: Vec<String> = vec![];
+
+        for name in names.into_iter().filter_map(|name| Hashtag::normalize_name(name)) {
+            if !normalized.contains(&name) {
+                normalized.push(name);
+            }
+        }
+
+        normalized
+    }
+
+    /// Find a hashtag by its name, which is normalized first.
+    pub async fn find_by_name<D>(name: &str, db: &D) -> Result<Document<Hashtag>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let not_found = || Error::NotFound {
+            model: "Hashtag".into(),
+            params: json!({"name": name})
+        };
+
+        let normalized = Hashtag::normalize_name(name).ok_or_else(not_found)?;
+
+        Hashtag::get(
+            &Hashtag::query()
+                .bind_var("name", normalized)
+                .filter(compare!(field "name").equals("@name").into()),
+            db,
+        )
+        .await?
+        .first_record()
+        .wrap()
+        .ok_or_else(not_found)
+    }
+
+    /// Find a hashtag by its name, or create it if it doesn't exist yet. If it's created by someone
+    /// else in the meantime, that one is found instead.
+    pub async fn find_or_create<D>(name: &str, db: &D) -> Result<Document<Hashtag>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        match Hashtag::find_by_name(name, db).await {
+            Ok(hashtag) => Ok(hashtag),
+            Err(e) if e.is_not_found() => {
+                let normalized = Hashtag::normalize_name(name)
+                    .ok_or_else(|| Error::Validation(format!("Invalid hashtag {name:?}").into()))?;
+
+                match Hashtag::create(Hashtag { name: normalized, created_at: None }, db).await {
+                    Ok(hashtag) => Ok(hashtag.wrap()),
+                    Err(e) => match Error::from(e) {
+                        e if e.is_conflict() => Hashtag::find_by_name(name, db).await,
+                        e => Err(e),
+                    },
+                }
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the latest public notes tagged with the hashtag, whether local or remote. Like
+    /// [Note::get_federated_timeline](crate::Note::get_federated_timeline), unlisted notes are
+    /// left out.
+    pub async fn get_timeline<D>(
+        name: &str,
+        options: TimelineOptions,
+        pagination: &Pagination,
+        db: &D
+    ) -> Result<Page<TimelineEntry>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let page = pagination.to_aql("note.created_at", "note._key");
+
+        let mut vars = pagination.bind_vars();
+        vars.insert("name", json!(Hashtag::normalize_name(name)));
+        vars.insert("public", json!(Recipient::Public));
+        vars.insert("exclude_replies", json!(options.exclude_replies));
+
+        let res: Vec<CursorItem<TimelineEntry>> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Account, Hashtag, Tagged, Note
+                FOR hashtag IN Hashtag
+                    FILTER hashtag.name == @name
+                    FOR note IN 1..1 INBOUND hashtag Tagged
+                        FILTER note.deleted_at == null
+                           AND @public IN note.to
+                        FILTER !@exclude_replies OR note.in_reply_to == null
+                        {page}
+                        RETURN {{
+                            cursor: {{ created_at: note.created_at, key: note._key }},
+                            item: {{
+                                note,
+                                author: DOCUMENT("Account", note.from),
+                                shared_by: null,
+                                created_at: note.created_at
+                            }}
+                        }}
+            "#), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(Page::from_rows(pagination, res))
+    }
+
+    fn before_create(&mut self) -> Result<(), aragog::Error> {
+        self.created_at = Some(Utc::now());
+        Ok(())
+    }
+}
@@ -1,19 +1,37 @@
 mod connection;
 mod wrappers;
 mod account;
+mod account_key;
+mod account_credential;
+mod access_token;
 mod note;
 mod edges;
 mod error;
 mod page_limit;
 mod cache;
+mod timeline;
+mod visibility;
+mod hashtag;
+mod content;
+mod media_attachment;
+mod profile;
 
 pub mod activitystreams;
 
 pub use crate::connection::*;
 pub use crate::wrappers::*;
 pub use crate::account::*;
+pub use crate::account_key::*;
+pub use crate::account_credential::*;
+pub use crate::access_token::*;
 pub use crate::note::*;
 pub use crate::edges::*;
 pub use crate::error::Error;
 pub use crate::page_limit::*;
 pub use crate::cache::*;
+pub use crate::timeline::*;
+pub use crate::visibility::*;
+pub use crate::hashtag::*;
+pub use crate::content::*;
+pub use crate::media_attachment::*;
+pub use crate::profile::*;
@@ -0,0 +1,275 @@
+use aragog::{DatabaseAccess, DatabaseRecord, Record};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::Url;
+use maplit::hashmap;
+
+use crate::{
+    Error,
+    Account,
+    Document,
+    Wrap,
+    activitystreams::UrlFor,
+    visibility::{can_view_aql, viewer_bind_vars},
+};
+
+/// Number of random bytes in a generated key. Keys are random so that files can't be found by
+/// guessing them.
+pub const MEDIA_ATTACHMENT_KEY_BYTES: usize = 24;
+
+/// An uploaded file, or a file that a remote note refers to.
+#[derive(Debug, Clone, Serialize, Deserialize, Record)]
+#[before_create(func = "before_create")]
+pub struct MediaAttachment {
+    /// Key of the account that uploaded it. Only set for local attachments.
+    #[serde(default)]
+    pub account: Option<String>,
+
+    /// Where the file can be found, for remote attachments. Local files are kept in storage, by
+    /// key.
+    #[serde(default)]
+    pub remote_url: Option<Url>,
+
+    /// MIME type, e.g. `image/png`.
+    pub media_type: String,
+
+    /// Size of the file in bytes. Only known for local attachments.
+    #[serde(default)]
+    pub size: Option<u64>,
+
+    #[serde(default)]
+    pub blurhash: Option<String>,
+
+    /// Alt text.
+    #[serde(default)]
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A media attachment as it's referenced by a note, with what's needed to describe it to others.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// MediaAttachment key. Only unset for remote attachments that haven't been recorded yet,
+    /// which [Note::publish](crate::Note::publish) does.
+    #[serde(default)]
+    pub media_attachment: Option<String>,
+
+    /// Set for remote attachments.
+    #[serde(default)]
+    pub remote_url: Option<Url>,
+
+    pub media_type: String,
+
+    #[serde(default)]
+    pub blurhash: Option<String>,
+
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl MediaAttachment {
+    /// Create media attachment data with required fields set.
+    pub fn new(media_type: String) -> MediaAttachment {
+        MediaAttachment {
+            account: None,
+            remote_url: None,
+            media_type,
+            size: None,
+            blurhash: None,
+            description: None,
+            created_at: None,
+        }
+    }
+
+    /// Generate a random key for a new media attachment. For local attachments, this is also the
+    /// key of the file in storage.
+    pub fn generate_key() -> String {
+        let mut bytes = [0u8; MEDIA_ATTACHMENT_KEY_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.remote_url.is_none()
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.remote_url.is_some()
+    }
+
+    /// Find a local media attachment that was uploaded by an account. Attachments uploaded by
+    /// others are reported as not found.
+    pub async fn find_uploaded_by<D>(
+        key: &str,
+        account_key: &str,
+        db: &D
+    ) -> Result<Document<MediaAttachment>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        match MediaAttachment::find(key, db).await {
+            Ok(record) if record.account.as_deref() == Some(account_key) => Ok(record.wrap()),
+            Ok(_) => Err(Error::NotFound {
+                model: "MediaAttachment".into(),
+                params: json!({"key": key, "account": account_key}),
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether the viewer can see a media attachment. Whoever uploaded it always can, and anyone
+    /// can see one that's used on a profile. Otherwise, it has to be attached to a note that the
+    /// viewer can see, the same as [Note::can_view](crate::Note::can_view).
+    pub async fn can_view<D>(
+        record: &Document<MediaAttachment>,
+        viewer: Option<&Document<Account>>,
+        db: &D
+    ) -> Result<bool, Error>
+    where
+        D: DatabaseAccess,
+    {
+        if let (Some(uploader), Some(viewer)) = (&record.account, viewer) {
+            if uploader == viewer.key() {
+                return Ok(true);
+            }
+        }
+
+        let mut vars = viewer_bind_vars(viewer);
+        vars.insert("key", json!(record.key()));
+
+        let mut res: Vec<bool> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Account, Note, Follow
+                LET on_profile = LENGTH(
+                    FOR account IN Account
+                        FILTER account.avatar.media_attachment == @key
+                            OR account.header.media_attachment == @key
+                        LIMIT 1
+                        RETURN true
+                ) > 0
+                LET on_visible_note = LENGTH(
+                    FOR note IN Note
+                        FILTER @key IN note.attachments[*].media_attachment
+                        FILTER note.deleted_at == null AND {can_view}
+                        LIMIT 1
+                        RETURN true
+                ) > 0
+                RETURN on_profile OR on_visible_note
+            "#, can_view = can_view_aql("note")), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(res.pop().unwrap_or(false))
+    }
+
+    /// Remove the media attachments with the given keys that are no longer attached to any note or
+    /// used on any profile. Their files must be removed from storage separately.
+    pub async fn delete_unused<D>(keys: &[String], db: &D) -> Result<(), Error>
+    where
+        D: DatabaseAccess,
+    {
+        let _: Vec<serde_json::Value> = db.database()
+            .aql_bind_vars(r#"
+                FOR media_attachment IN MediaAttachment
+                    FILTER media_attachment._key IN @keys
+                    FILTER LENGTH(
+                        FOR note IN Note
+                            FILTER media_attachment._key IN note.attachments[*].media_attachment
+                            LIMIT 1
+                            RETURN true
+                    ) == 0
+                    FILTER LENGTH(
+                        FOR account IN Account
+                            FILTER account.avatar.media_attachment == media_attachment._key
+                                OR account.header.media_attachment == media_attachment._key
+                            LIMIT 1
+                            RETURN true
+                    ) == 0
+                    REMOVE media_attachment IN MediaAttachment
+            "#, hashmap! {
+                "keys" => json!(keys)
+            })
+            .await.map_err(aragog::Error::from)?;
+        Ok(())
+    }
+
+    /// Record an uploaded attachment, under a key from [MediaAttachment::generate_key] that its
+    /// file was stored with.
+    pub async fn create_local<D>(
+        key: &str,
+        media_attachment: MediaAttachment,
+        db: &D
+    ) -> Result<Document<MediaAttachment>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Ok(DatabaseRecord::create_with_key(media_attachment, key.to_owned(), db).await?.wrap())
+    }
+
+    /// Record a remote attachment by its url, under the key that it's referred to by, which should
+    /// come from [MediaAttachment::generate_key].
+    pub async fn create_remote<D>(
+        attachment: &Attachment,
+        db: &D
+    ) -> Result<Document<MediaAttachment>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let key = attachment.media_attachment.clone()
+            .ok_or_else(|| Error::ConversionMissingField("media_attachment".into()))?;
+
+        let remote_url = attachment.remote_url.clone()
+            .ok_or_else(|| Error::ConversionMissingField("remote_url".into()))?;
+
+        Ok(DatabaseRecord::create_with_key(MediaAttachment {
+            remote_url: Some(remote_url),
+            blurhash: attachment.blurhash.clone(),
+            description: attachment.description.clone(),
+            ..MediaAttachment::new(attachment.media_type.clone())
+        }, key, db).await?.wrap())
+    }
+
+    fn before_create(&mut self) -> Result<(), aragog::Error> {
+        self.created_at = Some(Utc::now());
+        Ok(())
+    }
+}
+
+impl Attachment {
+    /// The ActivityStreams type to present the attachment as.
+    pub fn kind(&self) -> &'static str {
+        if self.media_type.starts_with("image/") {
+            "Image"
+        } else {
+            "Document"
+        }
+    }
+
+    /// Where the file can be found. Local attachments are served by us, and remote ones are where
+    /// they were found. Gives `None` if there's neither a key nor a remote url.
+    pub fn url_for<U>(&self, urls: &U) -> Result<Option<Url>, U::Error>
+    where
+        U: UrlFor,
+    {
+        match (&self.remote_url, &self.media_attachment) {
+            (Some(url), _) => Ok(Some(url.clone())),
+            (None, Some(key)) => urls.url_for_media_attachment(key).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+impl From<&Document<MediaAttachment>> for Attachment {
+    fn from(record: &Document<MediaAttachment>) -> Attachment {
+        Attachment {
+            media_attachment: Some(record.key().to_owned()),
+            remote_url: record.remote_url.clone(),
+            media_type: record.media_type.clone(),
+            blurhash: record.blurhash.clone(),
+            description: record.description.clone(),
+        }
+    }
+}
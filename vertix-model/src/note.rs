@@ -1,18 +1,54 @@
-use activitystreams::object;
+use activitystreams::{link, object, BaseBox};
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
-use aragog::{Record, DatabaseAccess, Validate, DatabaseRecord};
+use serde_json::json;
+use aragog::{compare, Record, DatabaseAccess, Validate, DatabaseRecord};
 use chrono::{DateTime, Utc, FixedOffset};
 use url::Url;
 use futures::stream::{FuturesOrdered, TryStreamExt};
-
-use crate::{Error, Account, Publish, Document, Wrap, activitystreams::{ToObject, UrlFor}};
+use maplit::hashmap;
+
+use crate::{
+    Error,
+    Account,
+    Publish,
+    Reply,
+    Tagged,
+    Hashtag,
+    Attachment,
+    MediaAttachment,
+    Document,
+    Wrap,
+    Pagination,
+    Page,
+    CursorItem,
+    TimelineEntry,
+    TimelineOptions,
+    activitystreams::{
+        ToObject,
+        UrlFor,
+        HashtagLink,
+        AttachmentObject,
+        get_ids,
+        get_hashtag_tags,
+        get_attachments,
+    },
+    Visibility,
+    NoteSource,
+    sanitize_html,
+    visibility::{can_view_aql, viewer_bind_vars},
+};
+
+/// How far up or down a thread [Note::get_ancestors] and [Note::get_descendants] will go.
+pub const MAX_THREAD_DEPTH: u32 = 100;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Recipient {
     Public,
     Account(String),
+    /// The followers of an account, by key.
+    Followers(String),
 }
 
 impl Recipient {
@@ -23,6 +59,7 @@ impl Recipient {
         match self {
             Recipient::Public => Ok(activitystreams::public().into()),
             Recipient::Account(key) => urls.url_for_account(key).await,
+            Recipient::Followers(key) => urls.url_for_account_followers(key).await,
         }
     }
 }
@@ -51,19 +88,75 @@ pub struct Note {
     #[serde(default)]
     pub bcc: Vec<Recipient>,
 
+    /// HTML. For local notes, this is rendered from `source`. For remote notes, it has been
+    /// sanitized with [sanitize_html].
+    #[serde(default)]
     pub content: String,
 
+    /// What the author wrote, for local notes.
+    #[serde(default)]
+    pub source: Option<NoteSource>,
+
+    /// Accounts mentioned in the content.
+    #[serde(default)]
+    pub mentions: Vec<Mention>,
+
+    /// Names of the hashtags the note is tagged with. Mirrored by [Tagged] edges.
+    #[serde(default)]
+    pub hashtags: Vec<String>,
+
+    /// Files attached to the note, in order.
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+
+    /// Key of the note this is a reply to. Mirrored by a [Reply] edge.
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+
     #[serde(default)]
     pub created_at: Option<DateTime<Utc>>,
 
     #[serde(default)]
     pub updated_at: Option<DateTime<Utc>>,
+
+    /// Set if the note has been deleted, in which case it's only kept as a tombstone.
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+
+    /// Prior revisions of the note, oldest first.
+    #[serde(default)]
+    pub revisions: Vec<NoteRevision>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteRevision {
+    pub content: String,
+
+    #[serde(default)]
+    pub source: Option<NoteSource>,
+
+    /// When this revision was published.
+    #[serde(default)]
+    pub revised_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mention {
+    /// Account key
+    pub account: String,
+
+    /// The mention as it's written, e.g. `@user@domain`.
+    pub name: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RemoteNoteInfo {
     /// The uri of the remote note.
     pub uri: Url,
+
+    /// The uri of the note this is a reply to, whether or not we have it.
+    #[serde(default)]
+    pub in_reply_to: Option<Url>,
 }
 
 impl Note {
@@ -77,8 +170,15 @@ impl Note {
             bto: vec![],
             bcc: vec![],
             content,
+            source: None,
+            mentions: vec![],
+            hashtags: vec![],
+            attachments: vec![],
+            in_reply_to: None,
             created_at: None,
             updated_at: None,
+            deleted_at: None,
+            revisions: vec![],
         }
     }
 
@@ -90,27 +190,454 @@ impl Note {
         self.remote.is_some()
     }
 
-    /// Publish a new note from the publisher. Creates a Note record and Publish edge.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    /// Add a mention of an account, which also makes it a recipient if it isn't one already. It
+    /// goes in `to` for direct notes, and `cc` otherwise.
+    pub fn add_mention(&mut self, account: &Document<Account>, name: String) {
+        if self.mentions.iter().any(|m| m.account == *account.key()) {
+            return;
+        }
+
+        self.mentions.push(Mention { account: account.key().to_owned(), name });
+
+        if !self.is_addressed_to(account.key()) {
+            let recipient = Recipient::Account(account.key().to_owned());
+
+            if self.visibility() == Visibility::Direct {
+                self.to.push(recipient);
+            } else {
+                self.cc.push(recipient);
+            }
+        }
+    }
+
+    /// Find a note by its URI. This only works for remote notes.
+    pub async fn find_by_uri<D>(uri: &Url, db: &D) -> Result<Document<Note>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Note::get(
+            &Note::query()
+                .bind_var("uri", uri.to_string())
+                .filter(compare!(field "remote.uri").equals("@uri").into()),
+            db,
+        )
+        .await?
+        .first_record()
+        .wrap()
+        .ok_or_else(|| Error::NotFound {
+            model: "Note".into(),
+            params: json!({"uri": uri})
+        })
+    }
+
+    /// Count the number of accounts that have liked this note.
+    pub async fn count_likes<D>(
+        record: &Document<Note>,
+        db: &D
+    ) -> Result<u64, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let mut res: Vec<u64> = db.database()
+            .aql_bind_vars(r#"
+                WITH Like
+                FOR edge in Like
+                    FILTER edge._to == @note_id
+                    COLLECT WITH COUNT INTO length
+                    RETURN length
+            "#, hashmap! {
+                "note_id" => json!(record.id())
+            })
+            .await.map_err(aragog::Error::from)?;
+        Ok(res.pop().unwrap_or(0))
+    }
+
+    /// Publish a new note from the publisher. Creates a Note record and Publish edge, a Reply
+    /// edge if `note.in_reply_to` is set, and a Tagged edge for each of `note.hashtags`. Remote
+    /// attachments that haven't been recorded yet get a MediaAttachment record.
     ///
-    /// `note.from` will be set to `publisher.key()`.
+    /// `note.from` will be set to `publisher.key()`, and `note.hashtags` will be normalized. If
+    /// the note being replied to doesn't exist or the publisher can't see it, nothing is created
+    /// and it's reported as not found.
     pub async fn publish<D>(
         publisher: &Document<Account>,
-        note: Note,
+        mut note: Note,
         db: &D
     ) -> Result<Document<Note>, Error>
     where
         D: DatabaseAccess,
     {
+        let parent = match note.in_reply_to {
+            Some(ref parent_key) => {
+                let parent: Document<Note> = Note::find(parent_key, db).await?.wrap();
+
+                if !Note::can_view(&parent, Some(publisher), db).await? {
+                    return Err(Error::NotFound {
+                        model: "Note".into(),
+                        params: json!({"key": parent_key}),
+                    });
+                }
+
+                Some(parent)
+            },
+            None => None,
+        };
+
+        // Remote attachments get their keys now, but are only recorded once the note exists, so
+        // that they're never left behind without it
+        let mut remote_attachments = vec![];
+
+        for attachment in &mut note.attachments {
+            if attachment.media_attachment.is_none() {
+                attachment.media_attachment = Some(MediaAttachment::generate_key());
+                remote_attachments.push(attachment.clone());
+            }
+        }
+
         let note = Note::create(Note {
             from: Some(publisher.key().into()),
+            hashtags: Hashtag::normalize_names(&note.hashtags),
             ..note
         }, db).await?;
 
+        for attachment in &remote_attachments {
+            MediaAttachment::create_remote(attachment, db).await?;
+        }
+
         DatabaseRecord::link(publisher, &note, db, Publish::default()).await?;
 
+        if let Some(ref parent) = parent {
+            DatabaseRecord::link(&note, parent, db, Reply::default()).await?;
+        }
+
+        Note::link_hashtags(&note, db).await?;
+
         Ok(note.wrap())
     }
 
+    /// Get the latest public notes that were published on this server.
+    pub async fn get_local_timeline<D>(
+        options: TimelineOptions,
+        pagination: &Pagination,
+        db: &D
+    ) -> Result<Page<TimelineEntry>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Note::get_public_timeline(true, options, pagination, db).await
+    }
+
+    /// Get the latest public notes that this server knows about, whether local or remote.
+    pub async fn get_federated_timeline<D>(
+        options: TimelineOptions,
+        pagination: &Pagination,
+        db: &D
+    ) -> Result<Page<TimelineEntry>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        Note::get_public_timeline(false, options, pagination, db).await
+    }
+
+    /// Get the latest public notes, optionally only the local ones. Public means
+    /// [Recipient::Public] is in `to`. Notes that only have it in `cc` are unlisted, and are left
+    /// out, as are shares.
+    async fn get_public_timeline<D>(
+        local_only: bool,
+        options: TimelineOptions,
+        pagination: &Pagination,
+        db: &D
+    ) -> Result<Page<TimelineEntry>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let page = pagination.to_aql("note.created_at", "note._key");
+
+        let mut vars = pagination.bind_vars();
+        vars.insert("public", json!(Recipient::Public));
+        vars.insert("local_only", json!(local_only));
+        vars.insert("exclude_replies", json!(options.exclude_replies));
+
+        let res: Vec<CursorItem<TimelineEntry>> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Account, Note
+                FOR note IN Note
+                    FILTER note.deleted_at == null
+                       AND @public IN note.to
+                    FILTER !@local_only OR note.remote == null
+                    FILTER !@exclude_replies OR note.in_reply_to == null
+                    {page}
+                    RETURN {{
+                        cursor: {{ created_at: note.created_at, key: note._key }},
+                        item: {{
+                            note,
+                            author: DOCUMENT("Account", note.from),
+                            shared_by: null,
+                            created_at: note.created_at
+                        }}
+                    }}
+            "#), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(Page::from_rows(pagination, res))
+    }
+
+    /// Get the direct replies to a note that the viewer can see.
+    pub async fn get_replies<D>(
+        record: &Document<Note>,
+        viewer: Option<&Document<Account>>,
+        pagination: &Pagination,
+        db: &D
+    ) -> Result<Page<Document<Note>>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let page = pagination.to_aql("note.created_at", "note._key");
+
+        let mut vars = pagination.bind_vars();
+        vars.extend(viewer_bind_vars(viewer));
+        vars.insert("note_id", json!(record.id()));
+
+        let res: Vec<CursorItem<Document<Note>>> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Note, Reply, Follow
+                FOR note IN 1..1 INBOUND @note_id Reply
+                    FILTER {can_view}
+                    {page}
+                    RETURN {{
+                        cursor: {{ created_at: note.created_at, key: note._key }},
+                        item: note
+                    }}
+            "#, can_view = can_view_aql("note")), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(Page::from_rows(pagination, res))
+    }
+
+    /// Count the number of direct replies to a note that the viewer can see.
+    pub async fn count_replies<D>(
+        record: &Document<Note>,
+        viewer: Option<&Document<Account>>,
+        db: &D
+    ) -> Result<u64, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let mut vars = viewer_bind_vars(viewer);
+        vars.insert("note_id", json!(record.id()));
+
+        let mut res: Vec<u64> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Note, Reply, Follow
+                FOR note IN 1..1 INBOUND @note_id Reply
+                    FILTER {can_view}
+                    COLLECT WITH COUNT INTO length
+                    RETURN length
+            "#, can_view = can_view_aql("note")), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(res.pop().unwrap_or(0))
+    }
+
+    /// Get the notes that this note is replying to, up to the start of the thread, in order
+    /// starting from the root. Notes that are deleted or that the viewer can't see are left out.
+    pub async fn get_ancestors<D>(
+        record: &Document<Note>,
+        viewer: Option<&Document<Account>>,
+        db: &D
+    ) -> Result<Vec<Document<Note>>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let mut vars = viewer_bind_vars(viewer);
+        vars.insert("note_id", json!(record.id()));
+        vars.insert("max_depth", json!(MAX_THREAD_DEPTH));
+
+        let res: Vec<Document<Note>> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Note, Reply, Follow
+                FOR note, edge, path IN 1..@max_depth OUTBOUND @note_id Reply
+                    FILTER note != null
+                    FILTER note.deleted_at == null AND {can_view}
+                    SORT LENGTH(path.edges) DESC
+                    RETURN note
+            "#, can_view = can_view_aql("note")), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(res)
+    }
+
+    /// Get all of the replies to this note, and their replies, in thread order (depth first,
+    /// oldest first among siblings). Notes that are deleted or that the viewer can't see are left
+    /// out, but their replies are not.
+    pub async fn get_descendants<D>(
+        record: &Document<Note>,
+        viewer: Option<&Document<Account>>,
+        db: &D
+    ) -> Result<Vec<Document<Note>>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        let mut vars = viewer_bind_vars(viewer);
+        vars.insert("note_id", json!(record.id()));
+        vars.insert("max_depth", json!(MAX_THREAD_DEPTH));
+
+        let res: Vec<Document<Note>> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Note, Reply, Follow
+                FOR note, edge, path IN 1..@max_depth INBOUND @note_id Reply
+                    FILTER note != null
+                    FILTER note.deleted_at == null AND {can_view}
+                    SORT path.vertices[*].created_at ASC
+                    RETURN note
+            "#, can_view = can_view_aql("note")), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(res)
+    }
+
+    /// Change the content and source of a note, keeping the previous ones in `revisions`. The
+    /// hashtags are replaced too, as they come from the content, and the Tagged edges follow.
+    ///
+    /// `updated_at` is only used for remote notes, as local notes are stamped when they're saved.
+    pub async fn edit<D>(
+        record: &mut Document<Note>,
+        content: String,
+        source: Option<NoteSource>,
+        hashtags: &[String],
+        updated_at: Option<DateTime<Utc>>,
+        db: &D
+    ) -> Result<(), Error>
+    where
+        D: DatabaseAccess,
+    {
+        let revision = NoteRevision {
+            revised_at: record.updated_at.or(record.created_at),
+            content: std::mem::replace(&mut record.content, content),
+            source: std::mem::replace(&mut record.source, source),
+        };
+
+        record.revisions.push(revision);
+        record.hashtags = Hashtag::normalize_names(hashtags);
+
+        if record.is_remote() {
+            record.updated_at = updated_at.or_else(|| Some(Utc::now()));
+        }
+
+        record.save(db).await?;
+
+        Note::unlink_hashtags(record, db).await?;
+        Note::link_hashtags(record, db).await?;
+        Ok(())
+    }
+
+    /// Create a Tagged edge for each of the note's `hashtags`.
+    async fn link_hashtags<D>(record: &DatabaseRecord<Note>, db: &D) -> Result<(), Error>
+    where
+        D: DatabaseAccess,
+    {
+        for name in &record.hashtags {
+            let hashtag = Hashtag::find_or_create(name, db).await?;
+
+            DatabaseRecord::link(record, &hashtag, db, Tagged::default()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove all of the note's Tagged edges.
+    async fn unlink_hashtags<D>(record: &DatabaseRecord<Note>, db: &D) -> Result<(), Error>
+    where
+        D: DatabaseAccess,
+    {
+        let _: Vec<serde_json::Value> = db.database()
+            .aql_bind_vars(r#"
+                FOR edge IN Tagged
+                    FILTER edge._from == @note_id
+                    REMOVE edge IN Tagged
+            "#, hashmap! {
+                "note_id" => json!(record.id())
+            })
+            .await.map_err(aragog::Error::from)?;
+        Ok(())
+    }
+
+    /// Delete a note. Its Publish, Share and Like edges are removed, and so are its media
+    /// attachments, unless they're still used elsewhere. Local files must be removed from storage
+    /// separately.
+    ///
+    /// Local notes are replaced with a tombstone, so that their url can tell others that they have
+    /// been deleted, and which keeps its place in the thread. Remote notes are removed entirely,
+    /// along with their Reply edges, and their replies no longer refer to them.
+    pub async fn delete_note<D>(
+        mut record: Document<Note>,
+        db: &D
+    ) -> Result<Document<Note>, Error>
+    where
+        D: DatabaseAccess,
+    {
+        for collection in ["Publish", "Share", "Like"] {
+            let _: Vec<serde_json::Value> = db.database()
+                .aql_bind_vars(r#"
+                    FOR edge IN @@collection
+                        FILTER edge._to == @note_id
+                        REMOVE edge IN @@collection
+                "#, hashmap! {
+                    "@collection" => json!(collection),
+                    "note_id" => json!(record.id())
+                })
+                .await.map_err(aragog::Error::from)?;
+        }
+
+        Note::unlink_hashtags(&record, db).await?;
+
+        let media_attachment_keys: Vec<String> = record.attachments.iter()
+            .filter_map(|attachment| attachment.media_attachment.clone())
+            .collect();
+
+        if record.is_remote() {
+            let _: Vec<serde_json::Value> = db.database()
+                .aql_bind_vars(r#"
+                    FOR edge IN Reply
+                        FILTER edge._to == @note_id
+                        UPDATE PARSE_IDENTIFIER(edge._from).key WITH { in_reply_to: null } IN Note
+                "#, hashmap! {
+                    "note_id" => json!(record.id())
+                })
+                .await.map_err(aragog::Error::from)?;
+
+            let _: Vec<serde_json::Value> = db.database()
+                .aql_bind_vars(r#"
+                    FOR edge IN Reply
+                        FILTER edge._from == @note_id OR edge._to == @note_id
+                        REMOVE edge IN Reply
+                "#, hashmap! {
+                    "note_id" => json!(record.id())
+                })
+                .await.map_err(aragog::Error::from)?;
+        }
+
+        if record.is_local() {
+            record.to.clear();
+            record.cc.clear();
+            record.bto.clear();
+            record.bcc.clear();
+            record.content.clear();
+            record.source = None;
+            record.mentions.clear();
+            record.hashtags.clear();
+            record.attachments.clear();
+            record.revisions.clear();
+            record.deleted_at = Some(Utc::now());
+            record.save(db).await?;
+        } else {
+            record.delete(db).await?;
+        }
+
+        MediaAttachment::delete_unused(&media_attachment_keys, db).await?;
+
+        Ok(record)
+    }
+
     fn before_create(&mut self) -> Result<(), aragog::Error> {
         if self.is_local() {
             self.created_at = Some(Utc::now());
@@ -144,21 +671,47 @@ impl ToObject for Document<Note> {
         U: UrlFor,
         E: From<Self::Error> + From<U::Error>,
     {
-        let (note_url, from_url, to_urls, cc_urls) =
-            futures::try_join!(
-                urls.url_for_note(self.key()),
-                async {
-                    if let Some(ref from) = self.from {
-                        Ok(Some(urls.url_for_account(&from).await?))
-                    } else {
-                        Ok(None)
-                    }
-                },
-                FuturesOrdered::from_iter(self.to.iter().map(|r| r.url_for(urls)))
-                    .try_collect::<Vec<_>>(),
-                FuturesOrdered::from_iter(self.cc.iter().map(|r| r.url_for(urls)))
-                    .try_collect::<Vec<_>>()
-            )?;
+        let (
+            note_url,
+            from_url,
+            in_reply_to_url,
+            to_urls,
+            cc_urls,
+            mention_urls,
+            hashtag_urls,
+            attachment_urls,
+        ) = futures::try_join!(
+            urls.url_for_note(self.key()),
+            async {
+                if let Some(ref from) = self.from {
+                    Ok(Some(urls.url_for_account(&from).await?))
+                } else {
+                    Ok(None)
+                }
+            },
+            async {
+                if let Some(ref parent) = self.in_reply_to {
+                    Ok(Some(urls.url_for_note(&parent).await?))
+                } else {
+                    Ok(self.remote.as_ref().and_then(|r| r.in_reply_to.clone()))
+                }
+            },
+            FuturesOrdered::from_iter(self.to.iter().map(|r| r.url_for(urls)))
+                .try_collect::<Vec<_>>(),
+            FuturesOrdered::from_iter(self.cc.iter().map(|r| r.url_for(urls)))
+                .try_collect::<Vec<_>>(),
+            FuturesOrdered::from_iter(
+                self.mentions.iter().map(|m| urls.url_for_account(&m.account)))
+                .try_collect::<Vec<_>>(),
+            async {
+                self.hashtags.iter().map(|name| urls.url_for_hashtag(name))
+                    .collect::<Result<Vec<_>, _>>()
+            },
+            async {
+                self.attachments.iter().map(|attachment| attachment.url_for(urls))
+                    .collect::<Result<Vec<_>, _>>()
+            }
+        )?;
 
         let mut note = object::Note::new();
 
@@ -179,11 +732,44 @@ impl ToObject for Document<Note> {
                 o.set_attributed_to_xsd_any_uri(from_url)?;
             }
 
+            if let Some(in_reply_to_url) = in_reply_to_url {
+                o.set_in_reply_to_xsd_any_uri(in_reply_to_url)?;
+            }
+
             o.set_many_to_xsd_any_uris(to_urls)?;
             o.set_many_cc_xsd_any_uris(cc_urls)?;
 
             o.set_content_xsd_string(self.content.clone())?;
 
+            let mut tags: Vec<BaseBox> = vec![];
+
+            for (mention, href) in self.mentions.iter().zip(mention_urls) {
+                let mut tag = link::Mention::new();
+                tag.link_props.set_href_xsd_any_uri(href)?;
+                tag.link_props.set_name_xsd_string(mention.name.clone())?;
+                tags.push(tag.try_into()?);
+            }
+
+            for (name, href) in self.hashtags.iter().zip(hashtag_urls) {
+                tags.push(HashtagLink::new(href, format!("#{name}")).try_into()?);
+            }
+
+            if !tags.is_empty() {
+                o.set_many_tag_base_boxes(tags)?;
+            }
+
+            let mut attachments: Vec<BaseBox> = vec![];
+
+            for (attachment, url) in self.attachments.iter().zip(attachment_urls) {
+                if let Some(url) = url {
+                    attachments.push(AttachmentObject::new(attachment, url).try_into()?);
+                }
+            }
+
+            if !attachments.is_empty() {
+                o.set_many_attachment_base_boxes(attachments)?;
+            }
+
             Ok::<_, Self::Error>(())
         })()?;
 
@@ -191,8 +777,55 @@ impl ToObject for Document<Note> {
     }
 }
 
+impl TryFrom<object::Note> for Note {
+    type Error = crate::error::Error;
+
+    /// Convert a remote note. `from` is not set, as the author has to be looked up by the caller,
+    /// and only the public recipient is kept in `to` and `cc`. The content is sanitized, and
+    /// attachments are taken by url.
+    fn try_from(note: object::Note) -> Result<Self, Self::Error> {
+        let missing = |s: &'static str| Error::ConversionMissingField(s.into());
+        let o = &note.object_props;
+        let id = o.get_id().ok_or(missing("id"))?;
+
+        let json = serde_json::to_value(&note)?;
+        let public = Url::from(activitystreams::public());
+        let public_only = |property: &str| -> Vec<Recipient> {
+            get_ids(&json, property).into_iter()
+                .filter(|uri| *uri == public)
+                .map(|_| Recipient::Public)
+                .take(1)
+                .collect()
+        };
+
+        Ok(Note {
+            from: None,
+            remote: Some(RemoteNoteInfo {
+                in_reply_to: get_ids(&json, "inReplyTo").into_iter().next(),
+                ..RemoteNoteInfo::new(id.as_url().clone())
+            }),
+            to: public_only("to"),
+            cc: public_only("cc"),
+            bto: vec![],
+            bcc: vec![],
+            content: o.get_content_xsd_string()
+                .map(|c| sanitize_html(&c.clone().into_string()))
+                .unwrap_or_default(),
+            source: None,
+            mentions: vec![],
+            hashtags: get_hashtag_tags(&json),
+            attachments: get_attachments(&json),
+            in_reply_to: None,
+            created_at: o.get_published().map(|d| d.as_datetime().clone().into()),
+            updated_at: o.get_updated().map(|d| d.as_datetime().clone().into()),
+            deleted_at: None,
+            revisions: vec![],
+        })
+    }
+}
+
 impl RemoteNoteInfo {
     pub fn new(uri: Url) -> RemoteNoteInfo {
-        RemoteNoteInfo { uri }
+        RemoteNoteInfo { uri, in_reply_to: None }
     }
 }
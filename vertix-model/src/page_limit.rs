@@ -1,34 +1,222 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
 use serde::{Serialize, Deserialize};
-use aragog::query::Query;
+use serde_json::{json, Value};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::Error;
+
+/// The largest number of items that can be requested in one page.
+pub const MAX_PAGE_LIMIT: u32 = 100;
+
+/// A position in a list sorted by `created_at` and then `_key`, used for keyset pagination.
+///
+/// As a string, this is `{created_at in RFC 3339}_{key}`, which clients should treat as opaque.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub created_at: DateTime<Utc>,
+    pub key: String,
+}
+
+impl Cursor {
+    pub fn new(created_at: DateTime<Utc>, key: impl Into<String>) -> Cursor {
+        Cursor { created_at, key: key.into() }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.created_at.to_rfc3339_opts(SecondsFormat::AutoSi, true), self.key)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Validation(format!("Invalid cursor: {s:?}").into());
+
+        let (created_at, key) = s.split_once('_').ok_or_else(invalid)?;
+        let created_at = DateTime::parse_from_rfc3339(created_at).map_err(|_| invalid())?;
+
+        if key.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Cursor::new(created_at.with_timezone(&Utc), key))
+    }
+}
+
+/// Which page of a list to get, by the cursors around it. The list is newest first.
+///
+/// - `max_id`: only items older than this.
+/// - `since_id`: only items newer than this, starting from the newest.
+/// - `min_id`: only items newer than this, starting from the ones right after it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pagination {
+    #[serde(default)]
+    pub max_id: Option<Cursor>,
+
+    #[serde(default)]
+    pub since_id: Option<Cursor>,
+
+    #[serde(default)]
+    pub min_id: Option<Cursor>,
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub struct PageLimit {
-    pub page: u32,
     pub limit: u32,
 }
 
-impl Default for PageLimit {
+impl Default for Pagination {
     fn default() -> Self {
-        PageLimit { page: 1, limit: 50 }
+        Pagination { max_id: None, since_id: None, min_id: None, limit: 50 }
     }
 }
 
-impl PageLimit {
-    pub fn offset(&self) -> u32 {
-        self.page.saturating_sub(1).saturating_mul(self.limit)
+impl Pagination {
+    /// The first page, with a given limit.
+    pub fn with_limit(limit: u32) -> Pagination {
+        Pagination { limit: limit.clamp(1, MAX_PAGE_LIMIT), ..Pagination::default() }
+    }
+
+    /// Whether the query has to run oldest first, because we want the items right after
+    /// `min_id`. The results are put back in newest first order afterward.
+    pub fn is_reversed(&self) -> bool {
+        self.min_id.is_some()
+    }
+
+    /// Generate the `FILTER`, `SORT` and `LIMIT` for a raw query. `created_at` and `key` are
+    /// expressions for the fields to paginate on, e.g. `edge.created_at` and `edge._key`. The
+    /// variables must be bound with [Pagination::bind_vars].
+    pub fn to_aql(&self, created_at: &str, key: &str) -> String {
+        let mut aql = String::new();
+
+        let mut filter = |name: &str, op: &str| {
+            aql.push_str(&format!(
+                "FILTER {created_at} {op} @{name}_created_at \
+                    OR ({created_at} == @{name}_created_at AND {key} {op} @{name}_key)\n"));
+        };
+
+        if self.max_id.is_some() {
+            filter("max", "<");
+        }
+        if self.since_id.is_some() {
+            filter("since", ">");
+        }
+        if self.min_id.is_some() {
+            filter("min", ">");
+        }
+
+        let direction = if self.is_reversed() { "ASC" } else { "DESC" };
+
+        aql.push_str(&format!("SORT {created_at} {direction}, {key} {direction}\nLIMIT @limit"));
+        aql
     }
 
-    pub fn to_aql(&self) -> String {
-        format!("LIMIT {offset}, {limit}", offset = self.offset(), limit = self.limit)
+    /// The bind vars needed by [Pagination::to_aql].
+    pub fn bind_vars(&self) -> HashMap<&'static str, Value> {
+        let mut vars = HashMap::new();
+
+        for (name, cursor) in [
+            (("max_created_at", "max_key"), &self.max_id),
+            (("since_created_at", "since_key"), &self.since_id),
+            (("min_created_at", "min_key"), &self.min_id),
+        ] {
+            if let Some(cursor) = cursor {
+                vars.insert(name.0, json!(cursor.created_at));
+                vars.insert(name.1, json!(cursor.key));
+            }
+        }
+
+        vars.insert("limit", json!(self.limit));
+        vars
+    }
+
+    /// Encode as a query string, for links to the page.
+    pub fn to_query(&self) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+
+        for (name, cursor) in [
+            ("max_id", &self.max_id),
+            ("since_id", &self.since_id),
+            ("min_id", &self.min_id),
+        ] {
+            if let Some(cursor) = cursor {
+                query.append_pair(name, &cursor.to_string());
+            }
+        }
+
+        if self.limit != Pagination::default().limit {
+            query.append_pair("limit", &self.limit.to_string());
+        }
+
+        query.finish()
     }
 }
 
-pub trait ApplyPageLimit {
-    fn apply_page_limit(self, page_limit: PageLimit) -> Self;
+/// A row from a query, along with its cursor. Queries using [Pagination] should return these, to
+/// be turned into a [Page].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorItem<T> {
+    pub cursor: Cursor,
+    pub item: T,
 }
 
-impl ApplyPageLimit for Query {
-    fn apply_page_limit(self, page_limit: PageLimit) -> Self {
-        self.limit(page_limit.limit, Some(page_limit.offset()))
+/// A page of a list from a query using [Pagination], newest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+
+    /// The cursor of the first (newest) item.
+    pub newest: Option<Cursor>,
+
+    /// The cursor of the last (oldest) item.
+    pub oldest: Option<Cursor>,
+
+    pub limit: u32,
+}
+
+impl<T> Page<T> {
+    /// Build a page from the rows returned by a query.
+    pub fn from_rows(pagination: &Pagination, mut rows: Vec<CursorItem<T>>) -> Page<T> {
+        if pagination.is_reversed() {
+            rows.reverse();
+        }
+
+        Page {
+            newest: rows.first().map(|row| row.cursor.clone()),
+            oldest: rows.last().map(|row| row.cursor.clone()),
+            items: rows.into_iter().map(|row| row.item).collect(),
+            limit: pagination.limit,
+        }
+    }
+
+    /// The page of older items, if there might be any.
+    pub fn next(&self) -> Option<Pagination> {
+        if self.items.len() < self.limit as usize {
+            return None;
+        }
+
+        self.oldest.clone().map(|oldest| Pagination {
+            max_id: Some(oldest),
+            ..Pagination::with_limit(self.limit)
+        })
+    }
+
+    /// The page of newer items. There's always a link to this if the page isn't empty, as newer
+    /// items could arrive at any time.
+    pub fn prev(&self) -> Option<Pagination> {
+        self.newest.clone().map(|newest| Pagination {
+            min_id: Some(newest),
+            ..Pagination::with_limit(self.limit)
+        })
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            newest: self.newest,
+            oldest: self.oldest,
+            limit: self.limit,
+        }
     }
 }
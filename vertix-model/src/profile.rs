@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Attachment};
+
+/// Maximum length of a display name, in characters.
+pub const DISPLAY_NAME_MAX_LENGTH: usize = 100;
+
+/// Maximum length of the source of a summary, in characters.
+pub const SUMMARY_MAX_LENGTH: usize = 2000;
+
+/// Maximum number of profile fields a local account can have.
+pub const PROFILE_FIELDS_MAX: usize = 4;
+
+/// Maximum length of the name or source value of a profile field, in characters.
+pub const PROFILE_FIELD_MAX_LENGTH: usize = 255;
+
+/// What an account shows about itself, besides its username.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Shown instead of the username where set.
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// HTML bio. For local accounts, this is rendered from `source`. For remote accounts, it has
+    /// been sanitized with [sanitize_html](crate::sanitize_html).
+    #[serde(default)]
+    pub summary: Option<String>,
+
+    #[serde(default)]
+    pub avatar: Option<Attachment>,
+
+    /// Banner image shown at the top of the profile.
+    #[serde(default)]
+    pub header: Option<Attachment>,
+
+    /// Values are HTML, like `summary`.
+    #[serde(default)]
+    pub fields: Vec<ProfileField>,
+
+    /// What a local account wrote for its summary and fields.
+    #[serde(default)]
+    pub source: Option<ProfileSource>,
+}
+
+/// A name/value pair shown on a profile, e.g. a link to a website.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileField {
+    pub name: String,
+    pub value: String,
+}
+
+/// The plain text that the summary and fields of a local profile are rendered from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileSource {
+    #[serde(default)]
+    pub summary: String,
+
+    #[serde(default)]
+    pub fields: Vec<ProfileField>,
+}
+
+impl Profile {
+    /// Check that a profile is acceptable for a local account.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |reason: String| Err(Error::Validation(reason.into()));
+
+        if let Some(ref display_name) = self.display_name {
+            if display_name.chars().count() > DISPLAY_NAME_MAX_LENGTH {
+                return invalid(format!("Display name must be at most {} characters long",
+                    DISPLAY_NAME_MAX_LENGTH));
+            }
+        }
+
+        if let Some(ref source) = self.source {
+            if source.summary.chars().count() > SUMMARY_MAX_LENGTH {
+                return invalid(format!("Summary must be at most {} characters long",
+                    SUMMARY_MAX_LENGTH));
+            }
+
+            if source.fields.len() > PROFILE_FIELDS_MAX {
+                return invalid(format!("There can be at most {} profile fields",
+                    PROFILE_FIELDS_MAX));
+            }
+
+            for field in &source.fields {
+                if field.name.trim().is_empty() {
+                    return invalid("Profile field names can't be empty".into());
+                }
+
+                if field.name.chars().count() > PROFILE_FIELD_MAX_LENGTH ||
+                    field.value.chars().count() > PROFILE_FIELD_MAX_LENGTH
+                {
+                    return invalid(format!("Profile fields must be at most {} characters long",
+                        PROFILE_FIELD_MAX_LENGTH));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
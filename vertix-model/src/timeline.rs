@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Account, Note, Document};
+
+/// A note as it appears on a timeline, along with who wrote it and who shared it there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub note: Document<Note>,
+
+    /// The account that published the note.
+    pub author: Document<Account>,
+
+    /// The account that shared the note, if it's on the timeline because of a share.
+    #[serde(default)]
+    pub shared_by: Option<Document<Account>>,
+
+    /// When the note was published or shared, whichever put it on the timeline.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Options that filter what appears on a timeline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineOptions {
+    /// Leave out notes that are replies to other notes.
+    #[serde(default)]
+    pub exclude_replies: bool,
+
+    /// Leave out notes that are only on the timeline because they were shared.
+    #[serde(default)]
+    pub exclude_shares: bool,
+}
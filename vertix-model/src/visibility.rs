@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use aragog::DatabaseAccess;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{Account, Document, Error, Note, Recipient};
+
+/// Who can see a note, as decided by its recipients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Public is in `to`. Anyone can see it, and it shows on public timelines.
+    Public,
+    /// Public is only in `cc`. Anyone can see it, but it's left off of public timelines.
+    Unlisted,
+    /// Addressed to followers. Only followers and anyone addressed directly can see it.
+    Followers,
+    /// Only the accounts it's addressed to can see it.
+    Direct,
+}
+
+impl Note {
+    /// Work out the visibility of the note from its recipients.
+    pub fn visibility(&self) -> Visibility {
+        let all = || self.to.iter().chain(&self.cc).chain(&self.bto).chain(&self.bcc);
+
+        if self.to.contains(&Recipient::Public) {
+            Visibility::Public
+        } else if all().any(|r| *r == Recipient::Public) {
+            Visibility::Unlisted
+        } else if all().any(|r| matches!(r, Recipient::Followers(_))) {
+            Visibility::Followers
+        } else {
+            Visibility::Direct
+        }
+    }
+
+    /// Fill in `to` and `cc` for the visibility, on behalf of the author. Accounts that are
+    /// already recipients are kept, but public and the author's followers are replaced.
+    ///
+    /// | visibility | `to`                  | `cc`                  |
+    /// |------------|-----------------------|-----------------------|
+    /// | public     | public                | followers             |
+    /// | unlisted   | followers             | public                |
+    /// | followers  | followers             |                       |
+    /// | direct     |                       |                       |
+    pub fn apply_visibility(&mut self, visibility: Visibility, author_key: &str) {
+        let followers = Recipient::Followers(author_key.to_owned());
+
+        for list in [&mut self.to, &mut self.cc, &mut self.bto, &mut self.bcc] {
+            list.retain(|r| *r != Recipient::Public && *r != followers);
+        }
+
+        let (mut to, mut cc) = match visibility {
+            Visibility::Public => (vec![Recipient::Public], vec![followers]),
+            Visibility::Unlisted => (vec![followers], vec![Recipient::Public]),
+            Visibility::Followers => (vec![followers], vec![]),
+            Visibility::Direct => (vec![], vec![]),
+        };
+
+        to.append(&mut self.to);
+        cc.append(&mut self.cc);
+
+        self.to = to;
+        self.cc = cc;
+    }
+
+    /// Whether the note can be shared. Only public and unlisted notes can, as sharing makes a note
+    /// public.
+    pub fn can_share(&self) -> bool {
+        matches!(self.visibility(), Visibility::Public | Visibility::Unlisted)
+    }
+
+    /// Whether the account is one of the recipients of the note, in any of the lists.
+    pub fn is_addressed_to(&self, account_key: &str) -> bool {
+        self.to.iter().chain(&self.cc).chain(&self.bto).chain(&self.bcc)
+            .any(|r| matches!(r, Recipient::Account(key) if key == account_key))
+    }
+
+    /// Whether the account wrote the note.
+    pub fn is_authored_by(&self, account_key: &str) -> bool {
+        self.from.as_deref() == Some(account_key)
+    }
+
+    /// Remove what only the author is allowed to see (`bto` and `bcc`), unless the viewer is the
+    /// author. This must be done before a note is sent to anyone else.
+    pub fn hide_private_recipients(&mut self, viewer_key: Option<&str>) {
+        if !viewer_key.map(|key| self.is_authored_by(key)).unwrap_or(false) {
+            self.bto.clear();
+            self.bcc.clear();
+        }
+    }
+
+    /// Check whether a note can be seen by the viewer, or by anyone if the viewer is `None`.
+    ///
+    /// This only looks at the recipients. Whether a deleted note should be shown as a tombstone
+    /// is left up to the caller.
+    pub async fn can_view<D>(
+        record: &Document<Note>,
+        viewer: Option<&Document<Account>>,
+        db: &D
+    ) -> Result<bool, Error>
+    where
+        D: DatabaseAccess,
+    {
+        match record.visibility() {
+            Visibility::Public | Visibility::Unlisted => return Ok(true),
+            _ => ()
+        }
+
+        let viewer = match viewer {
+            Some(viewer) => viewer,
+            None => return Ok(false),
+        };
+
+        if record.is_authored_by(viewer.key()) || record.is_addressed_to(viewer.key()) {
+            return Ok(true);
+        }
+
+        if record.visibility() == Visibility::Direct {
+            return Ok(false);
+        }
+
+        // Only left to check whether the viewer is a follower
+        let mut vars = viewer_bind_vars(Some(viewer));
+        vars.insert("note_id", json!(record.id()));
+
+        let mut res: Vec<bool> = db.database()
+            .aql_bind_vars(&format!(r#"
+                WITH Note, Follow
+                LET note = DOCUMENT(@note_id)
+                RETURN {}
+            "#, can_view_aql("note")), vars)
+            .await.map_err(aragog::Error::from)?;
+        Ok(res.pop().unwrap_or(false))
+    }
+}
+
+/// An AQL condition for whether the note in the variable `note` can be seen by the viewer, the
+/// same as [Note::can_view]. The variables must be bound with [viewer_bind_vars], and `Follow` must
+/// be in the `WITH` of the query.
+pub(crate) fn can_view_aql(note: &str) -> String {
+    format!(r#"(
+        @public IN {note}.to
+        OR @public IN {note}.cc
+        OR @public IN {note}.bto
+        OR @public IN {note}.bcc
+        OR (@viewer_key != null AND (
+            {note}.from == @viewer_key
+            OR @viewer_recipient IN {note}.to
+            OR @viewer_recipient IN {note}.cc
+            OR @viewer_recipient IN {note}.bto
+            OR @viewer_recipient IN {note}.bcc
+            OR LENGTH(
+                FOR recipient IN UNION({note}.to, {note}.cc, {note}.bto, {note}.bcc)
+                    FILTER IS_OBJECT(recipient) AND recipient.followers != null
+                    FOR follow IN Follow
+                        FILTER follow._from == @viewer_id
+                           AND follow._to == CONCAT("Account/", recipient.followers)
+                           AND follow.accepted == true
+                        LIMIT 1
+                        RETURN true
+            ) > 0
+        ))
+    )"#)
+}
+
+/// The bind vars needed by [can_view_aql]. A `None` viewer can only see what's public or unlisted.
+pub(crate) fn viewer_bind_vars(viewer: Option<&Document<Account>>) -> HashMap<&'static str, Value> {
+    let mut vars = HashMap::new();
+
+    vars.insert("public", json!(Recipient::Public));
+    vars.insert("viewer_key", json!(viewer.map(|v| v.key())));
+    vars.insert("viewer_id", json!(viewer.map(|v| v.id())));
+    vars.insert("viewer_recipient", json!(viewer.map(|v| Recipient::Account(v.key().to_owned()))));
+
+    vars
+}
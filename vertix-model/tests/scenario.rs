@@ -4,6 +4,14 @@ use anyhow::Result;
 use aragog::Record;
 use test_log::test;
 
+/// A note addressed to public, so that anyone can see it.
+fn public_note(content: String) -> Note {
+    Note {
+        to: vec![Recipient::Public],
+        ..Note::new(content)
+    }
+}
+
 #[test(actix_rt::test)]
 async fn follow_publish_and_view_timeline() -> Result<()> {
     let conn = create_connection().await?;
@@ -16,16 +24,17 @@ async fn follow_publish_and_view_timeline() -> Result<()> {
     follow.accepted = Some(true);
     follow.save(&conn).await?;
 
-    let note = Note::publish(&account2, Note::new("Hello, world!".into()), &conn).await?;
+    let note = Note::publish(&account2, public_note("Hello, world!".into()), &conn).await?;
 
-    let timeline = Account::get_timeline(&account1, PageLimit::default(), &conn).await?;
+    let timeline = Account::get_timeline(&account1, &Pagination::default(), &conn).await?;
 
     assert_eq!(timeline.len(), 1);
     assert!(timeline.iter().any(|t_note| t_note.key() == note.key()));
 
-    let note2 = Note::publish(&account2, Note::new("This is my second post.".into()), &conn).await?;
+    let note2 = Note::publish(
+        &account2, public_note("This is my second post.".into()), &conn).await?;
 
-    let timeline = Account::get_timeline(&account1, PageLimit::default(), &conn).await?;
+    let timeline = Account::get_timeline(&account1, &Pagination::default(), &conn).await?;
 
     assert_eq!(timeline.len(), 2);
     assert_eq!(timeline[0].id(), note2.id());
@@ -34,3 +43,848 @@ async fn follow_publish_and_view_timeline() -> Result<()> {
     Ok(())
 }
 
+
+#[test(actix_rt::test)]
+async fn create_local_account_with_keypair() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create_local(Account::new("account3".into()), &conn).await?;
+    let account_key = AccountKey::find_by_account(account.key(), &conn).await?;
+
+    assert_eq!(account.public_key_pem.as_ref(), Some(&account_key.public_key_pem));
+    assert!(account_key.private_key_pem.contains("PRIVATE KEY"));
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn receive_remote_note_into_timeline() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let uri: url::Url = "https://remote.example/users/remote1".parse()?;
+
+    let account = Account::create(Account::new("account4".into()), &conn).await?.wrap();
+    let remote_account = Account::create(Account {
+        domain: Some("remote.example".into()),
+        remote: Some(RemoteAccountInfo::new(uri.clone())),
+        ..Account::new("remote1".into())
+    }, &conn).await?.wrap();
+
+    let mut follow = Follow::link(&account, &remote_account, None, &conn).await?;
+
+    follow.accepted = Some(true);
+    follow.save(&conn).await?;
+
+    let note_uri: url::Url = "https://remote.example/notes/1".parse()?;
+
+    let note = Note::publish(&remote_account, Note {
+        remote: Some(RemoteNoteInfo::new(note_uri.clone())),
+        ..public_note("Hello from afar".into())
+    }, &conn).await?;
+
+    assert_eq!(Note::find_by_uri(&note_uri, &conn).await?.key(), note.key());
+
+    let timeline = Account::get_timeline(&account, &Pagination::default(), &conn).await?;
+
+    assert_eq!(timeline.len(), 1);
+    assert_eq!(timeline[0].key(), note.key());
+
+    // The same note can't be stored twice
+    assert!(Note::publish(&remote_account, Note {
+        remote: Some(RemoteNoteInfo::new(note_uri.clone())),
+        ..public_note("Hello from afar".into())
+    }, &conn).await.is_err());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn unfollow_removes_from_timeline() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account1 = Account::create(Account::new("account5".into()), &conn).await?.wrap();
+    let account2 = Account::create(Account::new("account6".into()), &conn).await?.wrap();
+
+    let mut follow = Follow::link(&account1, &account2, None, &conn).await?;
+
+    follow.accepted = Some(true);
+    follow.save(&conn).await?;
+
+    Note::publish(&account2, public_note("Hello, world!".into()), &conn).await?;
+
+    assert_eq!(Account::count_following(&account1, &conn).await?, 1);
+    assert_eq!(Account::get_timeline(&account1, &Pagination::default(), &conn).await?.len(), 1);
+
+    Follow::find_between(&account1, &account2, &conn).await?.delete(&conn).await?;
+
+    assert_eq!(Account::count_following(&account1, &conn).await?, 0);
+    assert_eq!(Account::count_followers(&account2, &conn).await?, 0);
+    assert!(Account::get_timeline(&account1, &Pagination::default(), &conn).await?.is_empty());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn like_and_count_likes() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account1 = Account::create(Account::new("account7".into()), &conn).await?.wrap();
+    let account2 = Account::create(Account::new("account8".into()), &conn).await?.wrap();
+
+    let note = Note::publish(&account2, Note::new("Like this post".into()), &conn).await?;
+
+    assert_eq!(Note::count_likes(&note, &conn).await?, 0);
+
+    Like::link(&account1, &note, None, &conn).await?;
+    Like::link(&account2, &note, None, &conn).await?;
+
+    assert_eq!(Note::count_likes(&note, &conn).await?, 2);
+
+    Like::find_between(&account1, &note, &conn).await?.delete(&conn).await?;
+
+    assert_eq!(Note::count_likes(&note, &conn).await?, 1);
+    assert!(Like::find_between(&account1, &note, &conn).await.is_err());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn shared_note_appears_in_timeline() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account1 = Account::create(Account::new("account9".into()), &conn).await?.wrap();
+    let account2 = Account::create(Account::new("account10".into()), &conn).await?.wrap();
+    let account3 = Account::create(Account::new("account11".into()), &conn).await?.wrap();
+
+    let mut follow = Follow::link(&account1, &account2, None, &conn).await?;
+
+    follow.accepted = Some(true);
+    follow.save(&conn).await?;
+
+    let note = Note::publish(&account3, public_note("Boost me".into()), &conn).await?;
+
+    assert!(Account::get_timeline(&account1, &Pagination::default(), &conn).await?.is_empty());
+
+    let share = Share::link(&account2, &note, None, &conn).await?;
+
+    let timeline = Account::get_timeline(&account1, &Pagination::default(), &conn).await?;
+
+    assert_eq!(timeline.len(), 1);
+    assert_eq!(timeline[0].key(), note.key());
+
+    share.delete(&conn).await?;
+
+    assert!(Account::get_timeline(&account1, &Pagination::default(), &conn).await?.is_empty());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn delete_note_leaves_tombstone() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account1 = Account::create(Account::new("account12".into()), &conn).await?.wrap();
+    let account2 = Account::create(Account::new("account13".into()), &conn).await?.wrap();
+
+    let note = Note::publish(&account1, Note::new("Oops".into()), &conn).await?;
+
+    Like::link(&account2, &note, None, &conn).await?;
+    Share::link(&account2, &note, None, &conn).await?;
+
+    let deleted = Note::delete_note(note.clone(), &conn).await?;
+
+    assert!(deleted.is_deleted());
+    assert!(deleted.content.is_empty());
+    assert_eq!(Note::count_likes(&note, &conn).await?, 0);
+    assert_eq!(Account::count_published_notes(&account1, Some(&account1), &conn).await?, 0);
+    assert!(Share::find_between(&account2, &note, &conn).await.is_err());
+
+    // The tombstone is still there
+    assert!(Note::find(note.key(), &conn).await?.is_deleted());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn edit_note_keeps_revisions() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create(Account::new("account14".into()), &conn).await?.wrap();
+
+    let mut note = Note::publish(&account, Note::new("Frist".into()), &conn).await?;
+
+    Note::edit(&mut note, "First".into(), None, &[], None, &conn).await?;
+    Note::edit(&mut note, "First!".into(), None, &[], None, &conn).await?;
+
+    let note = Note::find(note.key(), &conn).await?;
+
+    assert_eq!(note.content, "First!");
+    assert_eq!(note.revisions.len(), 2);
+    assert_eq!(note.revisions[0].content, "Frist");
+    assert_eq!(note.revisions[1].content, "First");
+    assert!(note.updated_at.is_some());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn reply_thread_context() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account15 = Account::create(Account::new("account15".into()), &conn).await?.wrap();
+    let account16 = Account::create(Account::new("account16".into()), &conn).await?.wrap();
+
+    let parent = Note::publish(&account15, public_note("Parent".into()), &conn).await?;
+
+    let reply = Note::publish(&account16, Note {
+        in_reply_to: Some(parent.key().into()),
+        ..public_note("Reply".into())
+    }, &conn).await?;
+
+    let reply_to_reply = Note::publish(&account15, Note {
+        in_reply_to: Some(reply.key().into()),
+        ..public_note("Reply to reply".into())
+    }, &conn).await?;
+
+    assert_eq!(Note::count_replies(&parent, None, &conn).await?, 1);
+    assert_eq!(Note::count_replies(&reply, None, &conn).await?, 1);
+
+    let ancestors = Note::get_ancestors(&reply_to_reply, None, &conn).await?;
+    assert_eq!(ancestors.iter().map(|n| n.key()).collect::<Vec<_>>(),
+        vec![parent.key(), reply.key()]);
+
+    let descendants = Note::get_descendants(&parent, None, &conn).await?;
+    assert_eq!(descendants.iter().map(|n| n.key()).collect::<Vec<_>>(),
+        vec![reply.key(), reply_to_reply.key()]);
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn password_login_issues_token() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create(Account::new("account17".into()), &conn).await?.wrap();
+
+    AccountCredential::set_password(account.key(), "correct horse", &conn).await?;
+
+    let credential = AccountCredential::find_by_account(account.key(), &conn).await?;
+    assert!(credential.verify_password("correct horse"));
+    assert!(!credential.verify_password("incorrect horse"));
+    assert!(!AccountCredential::verify_dummy_password("correct horse"));
+
+    let (token, access_token) = AccessToken::issue(account.key(), &conn).await?;
+
+    let found = AccessToken::find_by_token(&token, &conn).await?;
+    assert_eq!(found.key(), access_token.key());
+    assert_eq!(found.account, *account.key());
+
+    assert!(AccessToken::find_by_token("not a token", &conn).await.unwrap_err().is_not_found());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn register_enforces_username_rules() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let password_hash = AccountCredential::hash_password("correct horse")?;
+
+    let account = Account::register("Account18", password_hash.clone(), &conn).await?;
+    assert!(account.is_local());
+    assert!(account.public_key_pem.is_some());
+
+    let found = Account::find_by_username("account18", None, &conn).await?;
+    assert_eq!(found.key(), account.key());
+
+    let taken = Account::register("ACCOUNT18", password_hash.clone(), &conn).await;
+    assert!(matches!(taken, Err(Error::UsernameTaken(_))));
+
+    for username in ["", "inbox", "Admin", "has space", "émile", &"a".repeat(31)] {
+        let invalid = Account::register(username, password_hash.clone(), &conn).await;
+        assert!(matches!(invalid, Err(Error::Validation(_))), "{username:?} should be invalid");
+    }
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn home_timeline_entries_and_filters() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account19 = Account::create(Account::new("account19".into()), &conn).await?.wrap();
+    let account20 = Account::create(Account::new("account20".into()), &conn).await?.wrap();
+    let account21 = Account::create(Account::new("account21".into()), &conn).await?.wrap();
+
+    let mut follow = Follow::link(&account19, &account20, None, &conn).await?;
+    follow.accepted = Some(true);
+    follow.save(&conn).await?;
+
+    let own_note = Note::publish(&account19, public_note("My own post".into()), &conn).await?;
+    let shared_note = Note::publish(&account21, public_note("Shared post".into()), &conn).await?;
+    Share::link(&account20, &shared_note, None, &conn).await?;
+    let reply = Note::publish(&account20, Note {
+        in_reply_to: Some(own_note.key().into()),
+        ..public_note("A reply".into())
+    }, &conn).await?;
+
+    let timeline = Account::get_home_timeline(
+        &account19, TimelineOptions::default(), &Pagination::default(), &conn).await?;
+
+    assert_eq!(timeline.items.iter().map(|e| e.note.key()).collect::<Vec<_>>(),
+        vec![reply.key(), shared_note.key(), own_note.key()]);
+    assert_eq!(timeline.items[0].author.key(), account20.key());
+    assert_eq!(timeline.items[1].author.key(), account21.key());
+    assert_eq!(timeline.items[1].shared_by.as_ref().map(|a| a.key()), Some(account20.key()));
+    assert!(timeline.items[2].shared_by.is_none());
+
+    let timeline = Account::get_home_timeline(&account19, TimelineOptions {
+        exclude_replies: true,
+        exclude_shares: true,
+    }, &Pagination::default(), &conn).await?;
+
+    assert_eq!(timeline.items.iter().map(|e| e.note.key()).collect::<Vec<_>>(),
+        vec![own_note.key()]);
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn paginate_published_notes_with_cursors() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create(Account::new("account22".into()), &conn).await?.wrap();
+
+    let mut notes = vec![];
+    for n in 0..5 {
+        notes.push(Note::publish(&account, Note::new(format!("Note {n}")), &conn).await?);
+    }
+
+    let keys = |page: &Page<Document<Note>>| page.items.iter()
+        .map(|note| note.key().to_owned()).collect::<Vec<_>>();
+
+    let first = Account::get_published_notes(
+        &account, Some(&account), &Pagination::with_limit(2), &conn).await?;
+    assert_eq!(keys(&first), vec![notes[4].key().to_owned(), notes[3].key().to_owned()]);
+
+    let next = first.next().expect("first page should have a next page");
+    let second = Account::get_published_notes(&account, Some(&account), &next, &conn).await?;
+    assert_eq!(keys(&second), vec![notes[2].key().to_owned(), notes[1].key().to_owned()]);
+
+    // min_id gets the notes right after the cursor, still newest first
+    let prev = second.prev().expect("second page should have a prev page");
+    let back = Account::get_published_notes(&account, Some(&account), &prev, &conn).await?;
+    assert_eq!(keys(&back), keys(&first));
+
+    // Cursors survive being passed around as strings
+    let cursor: Cursor = next.max_id.as_ref().unwrap().to_string().parse()?;
+    assert_eq!(Some(&cursor), next.max_id.as_ref());
+
+    // Even with dates that don't fit in nanoseconds
+    let old = Cursor::new("1500-01-01T00:00:00Z".parse()?, "key");
+    assert_eq!(old.to_string().parse::<Cursor>()?, old);
+
+    let last = Account::get_published_notes(
+        &account, Some(&account), &second.next().unwrap(), &conn).await?;
+    assert_eq!(keys(&last), vec![notes[0].key().to_owned()]);
+    assert!(last.next().is_none());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn public_timelines_include_only_public_notes() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create(Account::new("account23".into()), &conn).await?.wrap();
+
+    let remote_account = Account::create(Account {
+        domain: Some("remote.example".into()),
+        remote: Some(RemoteAccountInfo::new("https://remote.example/users/remote2".parse()?)),
+        ..Account::new("remote2".into())
+    }, &conn).await?.wrap();
+
+    let public_note = Note::publish(&account, Note {
+        to: vec![Recipient::Public],
+        ..Note::new("Public".into())
+    }, &conn).await?;
+
+    let unlisted_note = Note::publish(&account, Note {
+        cc: vec![Recipient::Public],
+        ..Note::new("Unlisted".into())
+    }, &conn).await?;
+
+    let remote_note = Note::publish(&remote_account, Note {
+        to: vec![Recipient::Public],
+        remote: Some(RemoteNoteInfo::new("https://remote.example/notes/public-1".parse()?)),
+        ..Note::new("Remote public".into())
+    }, &conn).await?;
+
+    let local = Note::get_local_timeline(
+        TimelineOptions::default(), &Pagination::default(), &conn).await?;
+    let local_keys: Vec<_> = local.items.iter().map(|e| e.note.key()).collect();
+
+    assert!(local_keys.contains(&public_note.key()));
+    assert!(!local_keys.contains(&unlisted_note.key()));
+    assert!(!local_keys.contains(&remote_note.key()));
+
+    let federated = Note::get_federated_timeline(
+        TimelineOptions::default(), &Pagination::default(), &conn).await?;
+    let federated_keys: Vec<_> = federated.items.iter().map(|e| e.note.key()).collect();
+
+    assert!(federated_keys.contains(&public_note.key()));
+    assert!(federated_keys.contains(&remote_note.key()));
+    assert!(!federated_keys.contains(&unlisted_note.key()));
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn note_visibility_by_recipients_and_follows() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let author = Account::create(Account::new("account24".into()), &conn).await?.wrap();
+    let follower = Account::create(Account::new("account25".into()), &conn).await?.wrap();
+    let recipient = Account::create(Account::new("account26".into()), &conn).await?.wrap();
+    let stranger = Account::create(Account::new("account27".into()), &conn).await?.wrap();
+
+    let mut follow = Follow::link(&follower, &author, None, &conn).await?;
+    follow.accepted = Some(true);
+    follow.save(&conn).await?;
+
+    let followers_only = Note::publish(&author, Note {
+        to: vec![Recipient::Followers(author.key().into())],
+        ..Note::new("For followers".into())
+    }, &conn).await?;
+
+    let direct = Note::publish(&author, Note {
+        to: vec![Recipient::Account(recipient.key().into())],
+        bcc: vec![Recipient::Account(follower.key().into())],
+        ..Note::new("Secret".into())
+    }, &conn).await?;
+
+    assert_eq!(followers_only.visibility(), Visibility::Followers);
+    assert_eq!(direct.visibility(), Visibility::Direct);
+
+    assert!(!followers_only.can_share());
+    assert!(!direct.can_share());
+
+    assert!(!Note::can_view(&followers_only, None, &conn).await?);
+    assert!(Note::can_view(&followers_only, Some(&author), &conn).await?);
+    assert!(Note::can_view(&followers_only, Some(&follower), &conn).await?);
+    assert!(!Note::can_view(&followers_only, Some(&recipient), &conn).await?);
+
+    assert!(Note::can_view(&direct, Some(&recipient), &conn).await?);
+    assert!(Note::can_view(&direct, Some(&follower), &conn).await?);
+    assert!(!Note::can_view(&direct, Some(&stranger), &conn).await?);
+
+    assert_eq!(Account::count_published_notes(&author, None, &conn).await?, 0);
+    assert_eq!(Account::count_published_notes(&author, Some(&stranger), &conn).await?, 0);
+    assert_eq!(Account::count_published_notes(&author, Some(&follower), &conn).await?, 2);
+    assert_eq!(Account::count_published_notes(&author, Some(&recipient), &conn).await?, 1);
+
+    // Only the author gets to see bto/bcc
+    let mut seen_by_recipient = direct.clone();
+    seen_by_recipient.hide_private_recipients(Some(recipient.key().as_str()));
+    assert!(seen_by_recipient.bcc.is_empty());
+
+    let mut seen_by_author = direct.clone();
+    seen_by_author.hide_private_recipients(Some(author.key().as_str()));
+    assert_eq!(seen_by_author.bcc, direct.bcc);
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn publish_with_visibility_presets() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let author = Account::create(Account::new("account28".into()), &conn).await?.wrap();
+    let mentioned = Account::create(Account::new("account29".into()), &conn).await?.wrap();
+
+    let followers = Recipient::Followers(author.key().into());
+    let mention = Recipient::Account(mentioned.key().into());
+
+    for (visibility, to, cc) in [
+        (Visibility::Public, vec![Recipient::Public, mention.clone()], vec![followers.clone()]),
+        (Visibility::Unlisted, vec![followers.clone(), mention.clone()], vec![Recipient::Public]),
+        (Visibility::Followers, vec![followers.clone(), mention.clone()], vec![]),
+        (Visibility::Direct, vec![mention.clone()], vec![]),
+    ] {
+        let mut note = Note {
+            to: vec![Recipient::Public, mention.clone()],
+            ..Note::new("Hi".into())
+        };
+        note.apply_visibility(visibility, author.key());
+
+        let note = Note::publish(&author, note, &conn).await?;
+
+        assert_eq!(note.to, to);
+        assert_eq!(note.cc, cc);
+        assert_eq!(note.visibility(), visibility);
+    }
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn mentions_address_the_mentioned_account() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let author = Account::create(Account::new("account30".into()), &conn).await?.wrap();
+    let mentioned = Account::create(Account::new("account31".into()), &conn).await?.wrap();
+
+    let mut public = public_note("Hi @account31".into());
+    public.add_mention(&mentioned, "@account31".into());
+    public.add_mention(&mentioned, "@account31".into());
+
+    let public = Note::publish(&author, public, &conn).await?;
+
+    assert_eq!(public.mentions, vec![Mention {
+        account: mentioned.key().into(),
+        name: "@account31".into(),
+    }]);
+    assert_eq!(public.cc, vec![Recipient::Account(mentioned.key().into())]);
+    assert_eq!(public.visibility(), Visibility::Public);
+
+    let mut direct = Note::new("Psst @account31".into());
+    direct.add_mention(&mentioned, "@account31".into());
+
+    assert_eq!(direct.to, vec![Recipient::Account(mentioned.key().into())]);
+    assert!(Note::can_view(&Note::publish(&author, direct, &conn).await?, Some(&mentioned), &conn)
+        .await?);
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn hashtags_link_notes_to_tag_timelines() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create(Account::new("account32".into()), &conn).await?.wrap();
+
+    let tagged = Note::publish(&account, Note {
+        hashtags: vec!["Scenario32".into(), "#scenario32".into(), "32".into()],
+        ..public_note("Tagged #Scenario32".into())
+    }, &conn).await?;
+
+    let unlisted = Note::publish(&account, Note {
+        cc: vec![Recipient::Public],
+        hashtags: vec!["scenario32".into()],
+        ..Note::new("Unlisted #scenario32".into())
+    }, &conn).await?;
+
+    assert_eq!(tagged.hashtags, vec!["scenario32".to_owned()]);
+    assert_eq!(Hashtag::find_by_name("#SCENARIO32", &conn).await?.name, "scenario32");
+
+    let timeline = Hashtag::get_timeline(
+        "Scenario32", TimelineOptions::default(), &Pagination::default(), &conn).await?;
+
+    assert_eq!(timeline.items.iter().map(|e| e.note.key()).collect::<Vec<_>>(),
+        vec![tagged.key()]);
+    assert!(!timeline.items.iter().any(|e| e.note.key() == unlisted.key()));
+
+    let mut retagged = Note::publish(&account, Note {
+        hashtags: vec!["scenario32".into()],
+        ..public_note("Soon to be #other32".into())
+    }, &conn).await?;
+
+    Note::edit(&mut retagged, "Now #Other32".into(), None, &["Other32".into()], None, &conn)
+        .await?;
+
+    assert_eq!(retagged.hashtags, vec!["other32".to_owned()]);
+
+    let timeline = Hashtag::get_timeline(
+        "other32", TimelineOptions::default(), &Pagination::default(), &conn).await?;
+
+    assert_eq!(timeline.items.iter().map(|e| e.note.key()).collect::<Vec<_>>(),
+        vec![retagged.key()]);
+
+    Note::delete_note(retagged, &conn).await?;
+    Note::delete_note(tagged, &conn).await?;
+
+    assert!(Hashtag::get_timeline(
+        "scenario32", TimelineOptions::default(), &Pagination::default(), &conn).await?
+        .items.is_empty());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn attachments_are_recorded_when_published() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create(Account::new("account33".into()), &conn).await?.wrap();
+    let other = Account::create(Account::new("account34".into()), &conn).await?.wrap();
+
+    let key = MediaAttachment::generate_key();
+
+    let uploaded = MediaAttachment::create_local(&key, MediaAttachment {
+        account: Some(account.key().into()),
+        size: Some(5),
+        description: Some("A picture".into()),
+        ..MediaAttachment::new("image/png".into())
+    }, &conn).await?;
+
+    assert_eq!(uploaded.key(), &key);
+    assert_ne!(MediaAttachment::generate_key(), key);
+
+    assert!(MediaAttachment::find_uploaded_by(uploaded.key(), account.key(), &conn).await.is_ok());
+    assert!(MediaAttachment::find_uploaded_by(uploaded.key(), other.key(), &conn).await
+        .unwrap_err().is_not_found());
+
+    // Only the uploader can see it until it's attached to something
+    assert!(MediaAttachment::can_view(&uploaded, Some(&account), &conn).await?);
+    assert!(!MediaAttachment::can_view(&uploaded, Some(&other), &conn).await?);
+    assert!(!MediaAttachment::can_view(&uploaded, None, &conn).await?);
+
+    let remote_url: url::Url = "https://example.com/files/scenario33.pdf".parse()?;
+
+    let note = Note::publish(&account, Note {
+        attachments: vec![
+            Attachment::from(&uploaded),
+            Attachment {
+                media_attachment: None,
+                remote_url: Some(remote_url.clone()),
+                media_type: "application/pdf".into(),
+                blurhash: None,
+                description: None,
+            },
+        ],
+        ..public_note("With attachments".into())
+    }, &conn).await?;
+
+    assert_eq!(note.attachments.len(), 2);
+    assert_eq!(note.attachments[0].media_attachment.as_deref(), Some(uploaded.key().as_str()));
+    assert_eq!(note.attachments[0].kind(), "Image");
+    assert_eq!(note.attachments[1].kind(), "Document");
+
+    let remote_key = note.attachments[1].media_attachment.clone()
+        .expect("remote attachment should have been recorded");
+    let remote = MediaAttachment::find(&remote_key, &conn).await?;
+
+    assert_eq!(remote.remote_url, Some(remote_url));
+    assert!(remote.account.is_none());
+
+    assert!(MediaAttachment::can_view(&uploaded, None, &conn).await?);
+
+    // Attached to a note that only its author can see
+    let hidden = MediaAttachment::create_local(&MediaAttachment::generate_key(), MediaAttachment {
+        account: Some(account.key().into()),
+        ..MediaAttachment::new("image/gif".into())
+    }, &conn).await?;
+
+    Note::publish(&account, Note {
+        to: vec![Recipient::Account(account.key().into())],
+        attachments: vec![Attachment::from(&hidden)],
+        ..Note::new("Only for me".into())
+    }, &conn).await?;
+
+    assert!(!MediaAttachment::can_view(&hidden, Some(&other), &conn).await?);
+    assert!(!MediaAttachment::can_view(&hidden, None, &conn).await?);
+
+    Note::delete_note(note, &conn).await?;
+
+    assert!(MediaAttachment::find(uploaded.key(), &conn).await.is_err());
+    assert!(MediaAttachment::find(&remote_key, &conn).await.is_err());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn deleting_remote_parent_detaches_replies() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create(Account::new("account37".into()), &conn).await?.wrap();
+    let remote_account = Account::create(Account {
+        domain: Some("remote.example".into()),
+        remote: Some(RemoteAccountInfo::new("https://remote.example/users/remote8".parse()?)),
+        ..Account::new("remote8".into())
+    }, &conn).await?.wrap();
+
+    let parent = Note::publish(&remote_account, Note {
+        remote: Some(RemoteNoteInfo::new("https://remote.example/notes/parent-1".parse()?)),
+        ..public_note("Remote parent".into())
+    }, &conn).await?;
+
+    let reply = Note::publish(&account, Note {
+        in_reply_to: Some(parent.key().into()),
+        ..public_note("Reply".into())
+    }, &conn).await?;
+
+    Note::delete_note(parent, &conn).await?;
+
+    let reply: Document<Note> = Note::find(reply.key(), &conn).await?.wrap();
+
+    assert_eq!(reply.in_reply_to, None);
+    assert!(Note::get_ancestors(&reply, None, &conn).await?.is_empty());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn replies_to_missing_notes_are_rejected() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let account = Account::create(Account::new("account40".into()), &conn).await?.wrap();
+
+    let result = Note::publish(&account, Note {
+        in_reply_to: Some("missing".into()),
+        ..public_note("Reply".into())
+    }, &conn).await;
+
+    assert!(matches!(result, Err(e) if e.is_not_found()));
+    assert_eq!(Account::count_published_notes(&account, Some(&account), &conn).await?, 0);
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn replies_to_hidden_notes_are_rejected() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let author = Account::create(Account::new("account38".into()), &conn).await?.wrap();
+    let stranger = Account::create(Account::new("account39".into()), &conn).await?.wrap();
+
+    let direct = Note::publish(&author, Note::new("Just for me".into()), &conn).await?;
+
+    let result = Note::publish(&stranger, Note {
+        in_reply_to: Some(direct.key().into()),
+        ..public_note("Reply".into())
+    }, &conn).await;
+
+    assert!(matches!(result, Err(e) if e.is_not_found()));
+    assert_eq!(Account::count_published_notes(&stranger, Some(&stranger), &conn).await?, 0);
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn profiles_are_saved_and_refreshed() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let mut account = Account::create(Account::new("account35".into()), &conn).await?.wrap();
+
+    let profile = Profile {
+        display_name: Some("Account Thirty-Five".into()),
+        summary: Some("<p>Hello</p>".into()),
+        fields: vec![ProfileField { name: "Website".into(), value: "example.com".into() }],
+        source: Some(ProfileSource {
+            summary: "Hello".into(),
+            fields: vec![ProfileField { name: "Website".into(), value: "example.com".into() }],
+        }),
+        ..Profile::default()
+    };
+
+    profile.validate()?;
+
+    account.profile = profile.clone();
+    account.save(&conn).await?;
+
+    assert_eq!(Account::find_by_username("account35", None, &conn).await?.profile, profile);
+
+    let too_many_fields = Profile {
+        source: Some(ProfileSource {
+            summary: "".into(),
+            fields: vec![
+                ProfileField { name: "a".into(), value: "b".into() };
+                PROFILE_FIELDS_MAX + 1
+            ],
+        }),
+        ..Profile::default()
+    };
+
+    assert!(too_many_fields.validate().is_err());
+
+    let uri: url::Url = "https://remote.example/users/remote3".parse()?;
+
+    let mut remote_account = Account::create(Account {
+        domain: Some("remote.example".into()),
+        remote: Some(RemoteAccountInfo::new(uri.clone())),
+        ..Account::new("remote3".into())
+    }, &conn).await?.wrap();
+
+    remote_account.refresh_remote(Account {
+        domain: Some("remote.example".into()),
+        remote: Some(RemoteAccountInfo {
+            inbox: Some("https://remote.example/users/remote3/inbox".parse()?),
+            ..RemoteAccountInfo::new(uri.clone())
+        }),
+        profile: Profile {
+            display_name: Some("Remote Three".into()),
+            ..Profile::default()
+        },
+        ..Account::new("renamed".into())
+    });
+    remote_account.save(&conn).await?;
+
+    let remote_account = Account::find_by_uri(&uri, &conn).await?;
+
+    assert_eq!(remote_account.username, "remote3");
+    assert_eq!(remote_account.profile.display_name.as_deref(), Some("Remote Three"));
+    assert!(remote_account.remote.as_ref().unwrap().inbox.is_some());
+
+    Ok(())
+}
+
+#[test(actix_rt::test)]
+async fn stale_remote_accounts_are_found_per_domain() -> Result<()> {
+    let conn = create_connection().await?;
+
+    let max_age = std::time::Duration::from_secs(60 * 60);
+    let fetched_at = |hours_ago| Some(chrono::Utc::now() - chrono::Duration::hours(hours_ago));
+
+    let mut stale = vec![];
+
+    for (username, last_fetched_at) in [
+        ("remote4", None),
+        ("remote5", fetched_at(48)),
+        ("remote6", fetched_at(2)),
+        ("remote7", fetched_at(0)),
+    ] {
+        let uri = format!("https://stale.example/users/{username}").parse()?;
+
+        let account = Account::create(Account {
+            domain: Some("stale.example".into()),
+            remote: Some(RemoteAccountInfo {
+                last_fetched_at,
+                ..RemoteAccountInfo::new(uri)
+            }),
+            ..Account::new(username.into())
+        }, &conn).await?.wrap();
+
+        stale.push(account.is_stale(max_age));
+    }
+
+    assert_eq!(stale, [true, true, true, false]);
+
+    let local_account = Account::create(Account::new("account36".into()), &conn).await?;
+
+    assert!(!local_account.is_stale(max_age));
+
+    // Tried recently but couldn't be fetched, so it's left alone until the attempt is stale too
+    let unreachable = Account::create(Account {
+        domain: Some("stale.example".into()),
+        remote: Some(RemoteAccountInfo {
+            last_fetched_at: fetched_at(72),
+            ..RemoteAccountInfo::new("https://stale.example/users/remote9".parse()?)
+        }),
+        ..Account::new("remote9".into())
+    }, &conn).await?;
+
+    assert!(unreachable.is_stale(max_age));
+
+    Account::record_refresh_attempts(&[unreachable.key().clone()], &conn).await?;
+
+    assert!(!Account::find(unreachable.key(), &conn).await?.is_stale(max_age));
+
+    let found: Vec<String> = Account::find_stale_remote(max_age, 2, &conn).await?
+        .into_iter()
+        .filter(|account| account.domain.as_deref() == Some("stale.example"))
+        .map(|account| account.username.clone())
+        .collect();
+
+    assert_eq!(found, ["remote4", "remote5"]);
+
+    Ok(())
+}
@@ -1,29 +1,51 @@
+use std::sync::Arc;
+
 use anyhow::Result;
 use lapin::Channel;
+use vertix_app_common::{Config, Urls, http_signatures};
 use vertix_comm::messages::DeliverActivity;
+use vertix_model::{AragogConnectionManager, AccountKey, activitystreams::UrlFor};
 
 use crate::process_queue;
 
-pub async fn listen(ch: &Channel, client: reqwest::Client) -> Result<()> {
+pub async fn listen(
+    ch: &Channel,
+    config: Arc<Config>,
+    pool: bb8::Pool<AragogConnectionManager>,
+    client: reqwest::Client,
+) -> Result<()> {
     log::debug!("Listening for DeliverActivity");
 
     process_queue(ch, "DeliverActivity.process",
-        |data, _| process(data, &client)).await
+        |data, _| process(data, &*config, &pool, &client)).await
 }
 
 async fn process(
     data: DeliverActivity,
+    config: &Config,
+    pool: &bb8::Pool<AragogConnectionManager>,
     client: &reqwest::Client,
 ) -> Result<()> {
     log::debug!("Posting {data:?}");
 
+    let db = pool.get().await?;
+    let urls = Urls::new(&config.base_url, &*db);
+
+    let account = urls.account_cache.get(&data.from_account, &*db).await?;
+    let account_key = AccountKey::find_or_create(&account, &*db).await?;
+    let key_id = urls.url_for_account_public_key(&data.from_account).await?;
+
     let body = serde_json::to_vec(&data.activity)?;
 
-    client.post(data.inbox)
+    let res = http_signatures::signed_post(
+            client, data.inbox.clone(), body, key_id.as_str(), &account_key.private_key_pem)?
         .header(reqwest::header::CONTENT_TYPE, "application/activity+json")
-        .body(body)
         .send()
         .await?;
 
+    if !res.status().is_success() {
+        log::warn!("Delivery to {} was not accepted: {}", data.inbox, res.status());
+    }
+
     Ok(())
 }
@@ -14,6 +14,7 @@ mod log_interaction;
 mod send_interactions_to_remote;
 mod receive_activities;
 mod deliver_activities;
+mod refresh_accounts;
 
 #[actix_rt::main]
 async fn main() -> Result<()> {
@@ -47,11 +48,14 @@ async fn main() -> Result<()> {
     }
 
     start!(log_test_announce::listen);
-    start!(process_transaction::listen, pool = pool.clone(), client = reqwest.clone());
+    start!(process_transaction::listen,
+        config = config.clone(), pool = pool.clone(), client = reqwest.clone());
     start!(log_interaction::listen);
     start!(send_interactions_to_remote::listen, config = config.clone(), pool = pool.clone());
     start!(receive_activities::listen, config = config.clone(), pool = pool.clone());
-    start!(deliver_activities::listen, client = reqwest.clone());
+    start!(deliver_activities::listen,
+        config = config.clone(), pool = pool.clone(), client = reqwest.clone());
+    start!(refresh_accounts::run, config = config.clone(), pool = pool.clone());
 
     info!("ready.");
 
@@ -1,33 +1,50 @@
 use std::sync::Arc;
 
-use activitystreams::{ext::Ext, actor::{Person, properties::ApActorProperties}};
 use anyhow::{Result, anyhow, bail};
 use aragog::{Record, EdgeRecord};
 use chrono::Utc;
+use activitystreams::object;
+use url::Url;
 use lapin::Channel;
 
 use log::{warn, debug};
 use vertix_comm::{SendMessage, Delivery};
 use vertix_comm::messages::{Transaction, Action, Interaction, TransactionResponse, ActionResponse};
-use vertix_model::{AragogConnectionManager, Note, Account, Follow, Wrap, Edge};
+use vertix_model::{
+    AragogConnectionManager,
+    Note,
+    Hashtag,
+    MediaAttachment,
+    Account,
+    Follow,
+    Like,
+    Share,
+    Wrap,
+    Edge,
+    Document,
+};
+use vertix_model::activitystreams::ActorObject;
+use vertix_app_common::Config;
 
 use crate::process_queue;
 
 pub async fn listen(
     ch: &Channel,
+    config: Arc<Config>,
     pool: bb8::Pool<AragogConnectionManager>,
     client: reqwest::Client
 ) -> Result<()> {
     debug!("Listening for Transaction");
 
     process_queue(ch, "Transaction.process",
-        |data, msg| execute(data, msg, ch, &pool, &client)).await
+        |data, msg| execute(data, msg, ch, &*config, &pool, &client)).await
 }
 
 async fn execute(
     transaction: Transaction,
     msg: Arc<Delivery<()>>,
     ch: &Channel,
+    config: &Config,
     pool: &bb8::Pool<AragogConnectionManager>,
     client: &reqwest::Client
 ) -> Result<()> {
@@ -41,7 +58,7 @@ async fn execute(
 
     let result = (async {
         for action in &transaction.actions {
-            let response = execute_action(action, &mut interactions, &*db, client).await?;
+            let response = execute_action(action, &mut interactions, config, &*db, client).await?;
             responses.push(response);
         }
         Ok::<(), anyhow::Error>(())
@@ -78,61 +95,247 @@ static CONTENT_TYPES: &[&str] = &[
 async fn execute_action(
     action: &Action,
     interactions: &mut Vec<Interaction>,
+    config: &Config,
     db: &aragog::DatabaseConnection,
     client: &reqwest::Client
 ) -> Result<ActionResponse> {
     match action {
+        Action::RegisterAccount { username, password_hash } => {
+            let account = Account::register(username, password_hash.clone(), db).await?;
+
+            Ok(ActionResponse::RegisterAccount(account))
+        },
+
         Action::FetchAccount(url) => {
-            // Get the remote copy of the account
+            let account = fetch_account(url, db, client).await?;
+
+            Ok(ActionResponse::FetchAccount(account))
+        },
+
+        Action::RecordRefreshAttempts(keys) => {
+            Account::record_refresh_attempts(keys, db).await?;
+
+            Ok(ActionResponse::RecordRefreshAttempts)
+        },
+
+        Action::UpdateProfile { account, profile } => {
+            let mut account = Account::find(account, db).await?.wrap();
+
+            if account.is_remote() {
+                bail!("Can't update the profile of remote account {}", account.key());
+            }
+
+            profile.validate()?;
+
+            let modified = account.profile != *profile;
+
+            if modified {
+                account.profile = profile.clone();
+                account.save(db).await?;
+
+                interactions.push(Interaction::UpdateAccount(account.clone()));
+            }
+
+            Ok(ActionResponse::UpdateProfile { modified, account })
+        },
+
+        Action::UpdateRemoteAccount(account_body) => {
+            let uri = &account_body.remote.as_ref()
+                .ok_or_else(|| anyhow!("account.remote must be set"))?.uri;
+
+            let account = match Account::find_by_uri(uri, db).await {
+                Ok(mut account) => {
+                    account.refresh_remote(account_body.clone());
+                    account.save(db).await?;
+
+                    interactions.push(Interaction::UpdateAccount(account.clone()));
+                    Some(account)
+                },
+                Err(e) if e.is_not_found() => None,
+                Err(e) => return Err(e.into()),
+            };
+
+            Ok(ActionResponse::UpdateRemoteAccount(account))
+        },
+
+        Action::FetchNote(url) => {
+            if config.is_own_url(url) {
+                bail!("Can't fetch our own note: {url}");
+            }
+
+            // Get the remote copy of the note
             let res = client.get(url.clone())
                 .header(reqwest::header::ACCEPT, CONTENT_TYPES.join(", "))
                 .send()
                 .await?
                 .error_for_status()?;
 
-            let person: Ext<Person, ApActorProperties> = res.json().await?;
-            let mut account_body: Account = person.try_into()?;
-            let mut account;
+            let note_object: object::Note = res.json().await?;
 
-            // We just fetched it, so set the right fetch time
-            account_body.remote.as_mut().unwrap().last_fetched_at = Some(Utc::now());
+            let author_url = note_object.object_props.get_attributed_to_xsd_any_uri()
+                .ok_or_else(|| anyhow!("Fetched note has no attributedTo: {url}"))?
+                .as_url().clone();
 
-            // Ensure the person's url matches the one we requested
-            if account_body.remote.as_ref().unwrap().uri != *url {
-                bail!("Fetched account URL does not match the one requested, url={}, fetched={:?}",
-                    url, account_body);
+            // Only the author's own server can say that they wrote a note
+            if author_url.host() != url.host() || config.is_own_url(&author_url) {
+                bail!("Fetched note {url} is attributed to an account on another server: \
+                    {author_url}");
             }
 
-            // Try to get an existing account
-            match Account::find_by_uri(url, db).await {
-                Ok(existing_account) => {
-                    // Update the existing account
-                    account = existing_account;
-                    account.remote = account_body.remote.clone();
-                    account.updated_at = account_body.updated_at.clone();
-                    account.save(db).await?;
-                },
+            let mut note_body: Note = note_object.try_into()?;
+
+            // Ensure the note's url matches the one we requested
+            if note_body.remote.as_ref().unwrap().uri != *url {
+                bail!("Fetched note URL does not match the one requested, url={}, fetched={:?}",
+                    url, note_body);
+            }
+
+            let note = match Note::find_by_uri(url, db).await {
+                Ok(existing_note) => existing_note,
                 Err(e) if e.is_not_found() => {
-                    // Create a new account
-                    account = Account::create(account_body, db).await?.wrap();
+                    let author = match Account::find_by_uri(&author_url, db).await {
+                        Ok(author) => author,
+                        Err(e) if e.is_not_found() => fetch_account(&author_url, db, client).await?,
+                        Err(e) => return Err(e.into()),
+                    };
+
+                    // Remote notes without a published date, or with one in the future, are
+                    // sorted as if they were published now
+                    let now = Utc::now();
+                    note_body.created_at =
+                        Some(note_body.created_at.map_or(now, |created_at| created_at.min(now)));
+
+                    // Link the reply to its parent, if we have it and the author can see it
+                    let parent_uri = note_body.remote.as_ref().unwrap().in_reply_to.clone();
+                    if let Some(parent_uri) = parent_uri {
+                        if let Ok(parent) = Note::find_by_uri(&parent_uri, db).await {
+                            if Note::can_view(&parent, Some(&author), db).await? {
+                                note_body.in_reply_to = Some(parent.key().to_owned());
+                            }
+                        }
+                    }
+
+                    let note = Note::publish(&author, note_body, db).await?;
+                    interactions.push(Interaction::Note(note.clone()));
+                    note
                 },
                 Err(e) => return Err(e.into()),
+            };
+
+            Ok(ActionResponse::FetchNote(note))
+        },
+
+        Action::CreateMediaAttachment { key, media_attachment } => {
+            if media_attachment.account.is_none() {
+                bail!("media_attachment.account must be set");
             }
 
-            Ok(ActionResponse::FetchAccount(account))
+            let media_attachment =
+                MediaAttachment::create_local(key, media_attachment.clone(), db).await?;
+            Ok(ActionResponse::CreateMediaAttachment(media_attachment.wrap()))
         },
 
         Action::PublishNote(note) => {
             let from = note.from.as_ref()
                 .ok_or_else(|| anyhow!("note.from must be set"))?;
 
+            if note.is_remote() {
+                bail!("Remote notes are received, not published");
+            }
+
             let account = Account::find(from, db).await?.wrap();
 
+            if account.is_remote() {
+                bail!("Can't publish a note for remote account {}", account.key());
+            }
+
             let note_doc = Note::publish(&account, note.clone(), db).await?;
             interactions.push(Interaction::Note(note_doc.clone()));
             Ok(ActionResponse::PublishNote(note_doc))
         },
 
+        Action::ReceiveNote(note) => {
+            let from = note.from.as_ref()
+                .ok_or_else(|| anyhow!("note.from must be set"))?;
+
+            let uri = &note.remote.as_ref()
+                .ok_or_else(|| anyhow!("note.remote must be set"))?.uri;
+
+            let created;
+            let note_doc;
+
+            match Note::find_by_uri(uri, db).await {
+                Ok(existing_note) => {
+                    note_doc = existing_note;
+                    created = false;
+                },
+                Err(e) if e.is_not_found() => {
+                    let account = Account::find(from, db).await?.wrap();
+
+                    // The same note can arrive at more than one inbox at once, so if it was stored
+                    // in the meantime, that one is used instead
+                    match Note::publish(&account, note.clone(), db).await {
+                        Ok(published_note) => {
+                            note_doc = published_note;
+                            created = true;
+
+                            interactions.push(Interaction::Note(note_doc.clone()));
+                        },
+                        Err(e) if e.is_conflict() => {
+                            note_doc = Note::find_by_uri(uri, db).await?;
+                            created = false;
+                        },
+                        Err(e) => return Err(e.into()),
+                    }
+                },
+                Err(e) => return Err(e.into()),
+            }
+
+            Ok(ActionResponse::ReceiveNote { created, note: note_doc })
+        },
+
+        Action::EditNote { key, content, source, hashtags, updated_at } => {
+            let mut note = Note::find(key, db).await?.wrap();
+
+            if note.is_deleted() {
+                bail!("Can't edit deleted note {key}");
+            }
+
+            // Updates of remote notes can arrive out of order, and an older one mustn't replace
+            // what a newer one already set
+            let stale = note.is_remote() &&
+                match (updated_at, note.updated_at.or(note.created_at)) {
+                    (Some(updated_at), Some(stored_at)) => *updated_at <= stored_at,
+                    _ => false,
+                };
+
+            let modified = !stale && (note.content != *content || note.source != *source ||
+                note.hashtags != Hashtag::normalize_names(hashtags));
+
+            if modified {
+                Note::edit(&mut note, content.clone(), source.clone(), hashtags,
+                    updated_at.clone(), db).await?;
+
+                interactions.push(Interaction::EditNote(note.clone()));
+            }
+
+            Ok(ActionResponse::EditNote { modified, note })
+        },
+
+        Action::DeleteNote(key) => {
+            let note = Note::find(key, db).await?.wrap();
+
+            if note.is_deleted() {
+                return Ok(ActionResponse::DeleteNote { deleted: false, note });
+            }
+
+            let deleted_note = Note::delete_note(note.clone(), db).await?;
+
+            interactions.push(Interaction::DeleteNote(note));
+
+            Ok(ActionResponse::DeleteNote { deleted: true, note: deleted_note })
+        },
+
         Action::InitiateFollow { from_account, to_account, uri } => {
             let actor = Account::find(&from_account, db).await?;
             let target = Account::find(&to_account, db).await?;
@@ -169,6 +372,221 @@ async fn execute_action(
             }
 
             Ok(ActionResponse::SetFollowAccepted { modified, follow })
+        },
+
+        Action::RemoveFollow { from_account, to_account } => {
+            let actor = Account::find(&from_account, db).await?;
+            let target = Account::find(&to_account, db).await?;
+
+            let removed;
+
+            match Follow::find_between(&actor, &target, db).await {
+                Ok(follow) => {
+                    follow.delete(db).await?;
+
+                    interactions.push(Interaction::RemoveFollow(follow.clone()));
+                    removed = Some(follow);
+                },
+                Err(e) if e.is_not_found() => {
+                    removed = None;
+                },
+                Err(e) => return Err(e.into()),
+            }
+
+            Ok(ActionResponse::RemoveFollow { removed })
+        },
+
+        Action::LikeNote { account, note, uri } => {
+            let actor = Account::find(&account, db).await?;
+            let note = Note::find(&note, db).await?;
+
+            let created;
+            let like;
+
+            match Like::find_between(&actor, &note, db).await {
+                Ok(found_like) => {
+                    like = found_like;
+                    created = false;
+                },
+                Err(e) if e.is_not_found() => {
+                    // Another Like of the same note by the same account may have been stored in
+                    // the meantime, which the unique index catches
+                    match Like::link(&actor, &note, uri.clone(), db).await {
+                        Ok(new_like) => {
+                            like = new_like;
+                            created = true;
+
+                            interactions.push(Interaction::Like {
+                                like: like.clone(),
+                                note: note.wrap(),
+                            });
+                        },
+                        Err(e) if e.is_conflict() => {
+                            like = Like::find_between(&actor, &note, db).await?;
+                            created = false;
+                        },
+                        Err(e) => return Err(e.into()),
+                    }
+                },
+                Err(e) => return Err(e.into()),
+            }
+
+            Ok(ActionResponse::LikeNote { created, like })
+        },
+
+        Action::UnlikeNote { account, note } => {
+            let actor = Account::find(&account, db).await?;
+            let note = Note::find(&note, db).await?;
+
+            let removed;
+
+            match Like::find_between(&actor, &note, db).await {
+                Ok(like) => {
+                    like.delete(db).await?;
+
+                    interactions.push(Interaction::Unlike { like: like.clone(), note: note.wrap() });
+                    removed = Some(like);
+                },
+                Err(e) if e.is_not_found() => {
+                    removed = None;
+                },
+                Err(e) => return Err(e.into()),
+            }
+
+            Ok(ActionResponse::UnlikeNote { removed })
+        },
+
+        Action::ShareNote { account, note, uri } => {
+            let actor = Account::find(&account, db).await?;
+            let note = Note::find(&note, db).await?;
+
+            if !note.can_share() {
+                bail!("Note {} can't be shared, as it isn't public or unlisted", note.key());
+            }
+
+            let created;
+            let share;
+
+            if let Ok(found_share) = Share::find_between(&actor, &note, db).await {
+                share = found_share;
+                created = false;
+            } else {
+                share = Share::link(&actor, &note, uri.clone(), db).await?;
+                created = true;
+
+                interactions.push(Interaction::Share { share: share.clone(), note: note.wrap() });
+            }
+
+            Ok(ActionResponse::ShareNote { created, share })
+        },
+
+        Action::UnshareNote { account, note } => {
+            let actor = Account::find(&account, db).await?;
+            let note = Note::find(&note, db).await?;
+
+            let removed;
+
+            match Share::find_between(&actor, &note, db).await {
+                Ok(share) => {
+                    share.delete(db).await?;
+
+                    interactions.push(Interaction::Unshare { share: share.clone(), note: note.wrap() });
+                    removed = Some(share);
+                },
+                Err(e) if e.is_not_found() => {
+                    removed = None;
+                },
+                Err(e) => return Err(e.into()),
+            }
+
+            Ok(ActionResponse::UnshareNote { removed })
+        }
+    }
+}
+
+/// Get or update a remote account.
+async fn fetch_account(
+    url: &Url,
+    db: &aragog::DatabaseConnection,
+    client: &reqwest::Client
+) -> Result<Document<Account>> {
+    // Get the remote copy of the account
+    let res = client.get(url.clone())
+        .header(reqwest::header::ACCEPT, CONTENT_TYPES.join(", "))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let actor: ActorObject = res.json().await?;
+    let mut account_body: Account = actor.try_into()?;
+    let mut account;
+
+    // We just fetched it, so set the right fetch time
+    account_body.remote.as_mut().unwrap().last_fetched_at = Some(Utc::now());
+
+    // Ensure the person's url matches the one we requested
+    if account_body.remote.as_ref().unwrap().uri != *url {
+        bail!("Fetched account URL does not match the one requested, url={}, fetched={:?}",
+            url, account_body);
+    }
+
+    // Try to get an existing account
+    match Account::find_by_uri(url, db).await {
+        Ok(existing_account) => {
+            // Update the existing account
+            account = existing_account;
+            account.refresh_remote(account_body);
+            account.save(db).await?;
+        },
+        Err(e) if e.is_not_found() => {
+            // Create a new account
+            account = Account::create(account_body, db).await?.wrap();
+        },
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use vertix_model::{create_connection, Recipient, RemoteAccountInfo, RemoteNoteInfo};
+    use super::*;
+
+    #[actix_rt::test]
+    async fn receiving_the_same_note_twice_stores_it_once() -> Result<()> {
+        let db = create_connection().await?;
+        let config = Config::from_env()?;
+        let client = reqwest::Client::new();
+
+        let author = Account::create(Account {
+            domain: Some("remote.example".into()),
+            remote: Some(RemoteAccountInfo::new("https://remote.example/users/remote10".parse()?)),
+            ..Account::new("remote10".into())
+        }, &db).await?;
+
+        let action = Action::ReceiveNote(Note {
+            from: Some(author.key().into()),
+            to: vec![Recipient::Public],
+            remote: Some(RemoteNoteInfo::new("https://remote.example/notes/twice".parse()?)),
+            ..Note::new("Hello twice".into())
+        });
+
+        let mut interactions = vec![];
+
+        let first = execute_action(&action, &mut interactions, &config, &db, &client).await?;
+        let second = execute_action(&action, &mut interactions, &config, &db, &client).await?;
+
+        match (first, second) {
+            (
+                ActionResponse::ReceiveNote { created: true, note: first },
+                ActionResponse::ReceiveNote { created: false, note: second },
+            ) => assert_eq!(first.key(), second.key()),
+            responses => panic!("unexpected responses: {responses:?}"),
         }
+
+        assert_eq!(interactions.len(), 1);
+
+        Ok(())
     }
 }
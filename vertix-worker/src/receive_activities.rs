@@ -1,13 +1,15 @@
 use std::sync::Arc;
 
-use aragog::DatabaseConnection;
+use aragog::{DatabaseConnection, EdgeRecord};
 use lapin::Channel;
 use vertix_app_common::{helpers, Config};
 use vertix_comm::messages::{ReceiveActivity, Action};
-use vertix_model::AragogConnectionManager;
+use vertix_model::{AragogConnectionManager, Account, Note, Recipient, Follow, Like, Share, Document, Edge};
+use vertix_model::activitystreams::{ActorObject, get_ids, get_mention_tags};
 use anyhow::{bail, anyhow, Result};
+use chrono::Utc;
 use url::Url;
-use activitystreams::activity;
+use activitystreams::{activity, object};
 
 use crate::process_queue;
 
@@ -29,6 +31,17 @@ async fn process(
 
     match activity.kind() {
         Some("Follow") => process_follow(activity.into_concrete()?, config, ch, &*db).await?,
+        Some("Create") => process_create(activity.into_concrete()?, config, ch, &*db).await?,
+        Some("Accept") =>
+            process_follow_response(serde_json::to_value(&activity)?, true, config, ch, &*db).await?,
+        Some("Reject") =>
+            process_follow_response(serde_json::to_value(&activity)?, false, config, ch, &*db).await?,
+        Some("Like") => process_like(activity.into_concrete()?, config, ch, &*db).await?,
+        Some("Announce") =>
+            process_announce(serde_json::to_value(&activity)?, config, ch, &*db).await?,
+        Some("Update") => process_update(serde_json::to_value(&activity)?, config, ch, &*db).await?,
+        Some("Delete") => process_delete(serde_json::to_value(&activity)?, config, ch, &*db).await?,
+        Some("Undo") => process_undo(serde_json::to_value(&activity)?, config, ch, &*db).await?,
         _ => bail!("Unprocessable activity: {activity:?}")
     }
 
@@ -70,3 +83,559 @@ async fn process_follow(
 
     Ok(())
 }
+
+/// Process an Accept or Reject of one of our follows of a remote account.
+async fn process_follow_response(
+    activity: serde_json::Value,
+    accepted: bool,
+    config: &Config,
+    ch: &Channel,
+    db: &DatabaseConnection,
+) -> Result<()> {
+    log::debug!("Process remote follow response (accepted = {accepted}) {activity:?}");
+
+    let actor_uri = get_ids(&activity, "actor").into_iter().next()
+        .ok_or_else(|| anyhow!("Follow response actor is missing"))?;
+
+    let actor = helpers::find_or_fetch_account_by_uri(&actor_uri, &config, db, ch).await?;
+
+    let follow = find_follow_by_object(&activity, config, ch, db).await?
+        .ok_or_else(|| anyhow!("Follow not found for response from {actor_uri}"))?;
+
+    // Only the account being followed can respond to the follow
+    if follow.key_to() != actor.key() || !follow.to_remote {
+        bail!("{actor_uri} tried to respond to a follow that isn't to them: {follow:?}");
+    }
+
+    // Responses are only sent to the remote for follows from remote accounts, so this won't
+    // echo another Accept/Reject back
+    Action::SetFollowAccepted {
+        key: follow.key().to_owned(),
+        accepted,
+    }.send(ch).await?;
+
+    Ok(())
+}
+
+/// Find the follow that the object of an activity (like Accept or Undo) refers to.
+///
+/// The object is matched by its id first, which is either our own url for the follow or the uri
+/// the follow was created with. Some servers don't keep our id, so if that doesn't work and the
+/// Follow is embedded, it's matched by the actor/object pair instead. The caller must check that
+/// the actor is allowed to do anything with the follow.
+async fn find_follow_by_object(
+    activity: &serde_json::Value,
+    config: &Config,
+    ch: &Channel,
+    db: &DatabaseConnection,
+) -> Result<Option<Edge<Follow>>> {
+    if let Some(follow_uri) = get_ids(activity, "object").into_iter().next() {
+        let own_key = follow_uri.fragment()
+            .filter(|_| config.is_own_url(&follow_uri))
+            .and_then(|fragment| fragment.strip_prefix("follows/"));
+
+        let found = match own_key {
+            Some(key) => EdgeRecord::<Follow>::find(key, db).await.map(Edge::from)
+                .map_err(vertix_model::Error::from),
+            None => Follow::find_by_uri(&follow_uri, db).await,
+        };
+
+        match found {
+            Ok(follow) => return Ok(Some(follow)),
+            Err(e) if e.is_not_found() => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let embedded_uri = |property: &str| activity.get("object")
+        .and_then(|object| get_ids(object, property).into_iter().next());
+
+    let (follower_uri, target_uri) = match (embedded_uri("actor"), embedded_uri("object")) {
+        (Some(follower_uri), Some(target_uri)) => (follower_uri, target_uri),
+        _ => return Ok(None),
+    };
+
+    let follower = helpers::find_or_fetch_account_by_uri(&follower_uri, config, db, ch).await?;
+    let target = helpers::find_or_fetch_account_by_uri(&target_uri, config, db, ch).await?;
+
+    match Follow::find_between(&follower, &target, db).await {
+        Ok(follow) => Ok(Some(follow)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Process an Undo of a Follow, Like or Announce.
+async fn process_undo(
+    activity: serde_json::Value,
+    config: &Config,
+    ch: &Channel,
+    db: &DatabaseConnection,
+) -> Result<()> {
+    log::debug!("Process remote undo {activity:?}");
+
+    let actor_uri = get_ids(&activity, "actor").into_iter().next()
+        .ok_or_else(|| anyhow!("Undo actor is missing"))?;
+
+    let actor = helpers::find_or_fetch_account_by_uri(&actor_uri, &config, db, ch).await?;
+
+    let object_kind = activity.get("object")
+        .and_then(|object| object.get("type"))
+        .and_then(|kind| kind.as_str());
+
+    // A bare uri could be either, so try both
+    if matches!(object_kind, Some("Follow") | None) {
+        if let Some(follow) = find_follow_by_object(&activity, config, ch, db).await? {
+            // Only the follower can undo the follow
+            if follow.key_from() != actor.key() || !follow.from_remote {
+                bail!("{actor_uri} tried to undo a follow that isn't theirs: {follow:?}");
+            }
+
+            Action::RemoveFollow {
+                from_account: follow.key_from().to_owned(),
+                to_account: follow.key_to().to_owned(),
+            }.send(ch).await?;
+
+            return Ok(());
+        }
+    }
+
+    if matches!(object_kind, Some("Like") | None) {
+        if let Some(like) = find_like_by_object(&activity, &actor, config, db).await? {
+            if like.key_from() != actor.key() {
+                bail!("{actor_uri} tried to undo a like that isn't theirs: {like:?}");
+            }
+
+            Action::UnlikeNote {
+                account: like.key_from().to_owned(),
+                note: like.key_to().to_owned(),
+            }.send(ch).await?;
+
+            return Ok(());
+        }
+    }
+
+    if matches!(object_kind, Some("Announce") | None) {
+        if let Some(share) = find_share_by_object(&activity, &actor, config, db).await? {
+            if share.key_from() != actor.key() {
+                bail!("{actor_uri} tried to undo a share that isn't theirs: {share:?}");
+            }
+
+            Action::UnshareNote {
+                account: share.key_from().to_owned(),
+                note: share.key_to().to_owned(),
+            }.send(ch).await?;
+
+            return Ok(());
+        }
+    }
+
+    match object_kind {
+        Some("Follow") | Some("Like") | Some("Announce") | None => {
+            log::debug!("Nothing to undo for {actor_uri}");
+            Ok(())
+        },
+        Some(kind) => bail!("Unprocessable Undo object type: {kind}")
+    }
+}
+
+/// Process a Like of one of the notes we have.
+async fn process_like(
+    activity: activity::Like,
+    config: &Config,
+    ch: &Channel,
+    db: &DatabaseConnection,
+) -> Result<()> {
+    log::debug!("Process remote like {activity:?}");
+
+    let actor_uri: Url = activity.like_props.get_actor_xsd_any_uri()
+        .ok_or_else(|| anyhow!("Like actor is not URI"))?
+        .as_url().clone();
+
+    let object_uri: Url = activity.like_props.get_object_xsd_any_uri()
+        .ok_or_else(|| anyhow!("Like object is not URI"))?
+        .as_url().clone();
+
+    let like_uri: Option<Url> = activity.object_props.get_id().map(|u| u.as_url()).cloned();
+
+    let note = match helpers::find_note_by_uri(&object_uri, config, db).await {
+        Ok(note) => note,
+        Err(e) if e.is_not_found() => {
+            log::debug!("Ignoring like of unknown note {object_uri}");
+            return Ok(());
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    let actor = helpers::find_or_fetch_account_by_uri(&actor_uri, &config, db, ch).await?;
+
+    if actor.is_local() {
+        bail!("Remote server tried to like a note for local account {actor_uri}");
+    }
+
+    if !Note::can_view(&note, Some(&actor), db).await? {
+        log::debug!("Ignoring like of {object_uri} by {actor_uri}, who can't see it");
+        return Ok(());
+    }
+
+    Action::LikeNote {
+        account: actor.key().to_owned(),
+        note: note.key().to_owned(),
+        uri: like_uri,
+    }.send(ch).await?;
+
+    Ok(())
+}
+
+/// Find the like that the object of an Undo refers to, by its uri, or by the actor and the note
+/// if the Like is embedded.
+async fn find_like_by_object(
+    activity: &serde_json::Value,
+    actor: &Document<Account>,
+    config: &Config,
+    db: &DatabaseConnection,
+) -> Result<Option<Edge<Like>>> {
+    if let Some(like_uri) = get_ids(activity, "object").into_iter().next() {
+        match Like::find_by_uri(&like_uri, db).await {
+            Ok(like) => return Ok(Some(like)),
+            Err(e) if e.is_not_found() => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let embedded_note_uri = activity.get("object")
+        .and_then(|object| get_ids(object, "object").into_iter().next());
+
+    let note_uri = match embedded_note_uri {
+        Some(note_uri) => note_uri,
+        None => return Ok(None),
+    };
+
+    let note = match helpers::find_note_by_uri(&note_uri, config, db).await {
+        Ok(note) => note,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    match Like::find_between(actor, &note, db).await {
+        Ok(like) => Ok(Some(like)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Process an Announce (boost) of a note, fetching the note if we don't have it.
+async fn process_announce(
+    activity: serde_json::Value,
+    config: &Config,
+    ch: &Channel,
+    db: &DatabaseConnection,
+) -> Result<()> {
+    log::debug!("Process remote announce {activity:?}");
+
+    let actor_uri = get_ids(&activity, "actor").into_iter().next()
+        .ok_or_else(|| anyhow!("Announce actor is missing"))?;
+
+    let object_uri = get_ids(&activity, "object").into_iter().next()
+        .ok_or_else(|| anyhow!("Announce object is missing"))?;
+
+    let share_uri: Option<Url> = activity.get("id")
+        .and_then(|id| id.as_str())
+        .and_then(|id| id.parse().ok());
+
+    let actor = helpers::find_or_fetch_account_by_uri(&actor_uri, &config, db, ch).await?;
+
+    if actor.is_local() {
+        bail!("Remote server tried to announce a note for local account {actor_uri}");
+    }
+
+    let note = helpers::find_or_fetch_note_by_uri(&object_uri, &config, db, ch).await?;
+
+    if !note.can_share() || !Note::can_view(&note, Some(&actor), db).await? {
+        log::debug!("Ignoring announce of {object_uri} by {actor_uri}, as it can't be shared");
+        return Ok(());
+    }
+
+    Action::ShareNote {
+        account: actor.key().to_owned(),
+        note: note.key().to_owned(),
+        uri: share_uri,
+    }.send(ch).await?;
+
+    Ok(())
+}
+
+/// Find the share that the object of an Undo refers to, by its uri, or by the actor and the note
+/// if the Announce is embedded.
+async fn find_share_by_object(
+    activity: &serde_json::Value,
+    actor: &Document<Account>,
+    config: &Config,
+    db: &DatabaseConnection,
+) -> Result<Option<Edge<Share>>> {
+    if let Some(share_uri) = get_ids(activity, "object").into_iter().next() {
+        match Share::find_by_uri(&share_uri, db).await {
+            Ok(share) => return Ok(Some(share)),
+            Err(e) if e.is_not_found() => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let embedded_note_uri = activity.get("object")
+        .and_then(|object| get_ids(object, "object").into_iter().next());
+
+    let note_uri = match embedded_note_uri {
+        Some(note_uri) => note_uri,
+        None => return Ok(None),
+    };
+
+    let note = match helpers::find_note_by_uri(&note_uri, config, db).await {
+        Ok(note) => note,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    match Share::find_between(actor, &note, db).await {
+        Ok(share) => Ok(Some(share)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Process an Update of a remote note or actor. Only the author of the note, or the actor itself,
+/// can update it.
+async fn process_update(
+    activity: serde_json::Value,
+    config: &Config,
+    ch: &Channel,
+    db: &DatabaseConnection,
+) -> Result<()> {
+    log::debug!("Process remote update {activity:?}");
+
+    let actor_uri = get_ids(&activity, "actor").into_iter().next()
+        .ok_or_else(|| anyhow!("Update actor is missing"))?;
+
+    let object = activity.get("object")
+        .filter(|object| object.is_object())
+        .ok_or_else(|| anyhow!("Update object is not embedded"))?;
+
+    match object.get("type").and_then(|kind| kind.as_str()) {
+        Some("Note") => {
+            let note_object: object::Note = serde_json::from_value(object.clone())?;
+
+            let attributed_to = note_object.object_props.get_attributed_to_xsd_any_uri()
+                .map(|uri| uri.as_url().clone());
+
+            if attributed_to.as_ref() != Some(&actor_uri) {
+                bail!("Update actor {actor_uri} is not the author of the note");
+            }
+
+            let updated_note = Note::try_from(note_object)?;
+            let uri = &updated_note.remote.as_ref().unwrap().uri;
+
+            let note = match Note::find_by_uri(uri, db).await {
+                Ok(note) => note,
+                Err(e) if e.is_not_found() => {
+                    log::debug!("Ignoring update of unknown note {uri}");
+                    return Ok(());
+                },
+                Err(e) => return Err(e.into()),
+            };
+
+            let actor = helpers::find_or_fetch_account_by_uri(&actor_uri, &config, db, ch).await?;
+
+            if note.from.as_deref() != Some(actor.key().as_str()) {
+                bail!("{actor_uri} tried to update a note that isn't theirs: {uri}");
+            }
+
+            Action::EditNote {
+                key: note.key().to_owned(),
+                content: updated_note.content,
+                source: None,
+                hashtags: updated_note.hashtags,
+                updated_at: updated_note.updated_at,
+            }.send(ch).await?;
+        },
+        Some("Person") => {
+            let actor: ActorObject = serde_json::from_value(object.clone())?;
+
+            let mut account_body = Account::try_from(actor)?;
+            let remote = account_body.remote.as_mut().unwrap();
+
+            if remote.uri != actor_uri {
+                bail!("Update actor {actor_uri} tried to update another actor {}", remote.uri);
+            }
+
+            // This is as good as having fetched it
+            remote.last_fetched_at = Some(Utc::now());
+
+            Action::UpdateRemoteAccount(account_body).send(ch).await?;
+        },
+        kind => bail!("Unprocessable Update object type: {kind:?}")
+    }
+
+    Ok(())
+}
+
+/// Process a Delete of a remote note. Only the author of the note can delete it.
+async fn process_delete(
+    activity: serde_json::Value,
+    config: &Config,
+    ch: &Channel,
+    db: &DatabaseConnection,
+) -> Result<()> {
+    log::debug!("Process remote delete {activity:?}");
+
+    let actor_uri = get_ids(&activity, "actor").into_iter().next()
+        .ok_or_else(|| anyhow!("Delete actor is missing"))?;
+
+    let object_uri = get_ids(&activity, "object").into_iter().next()
+        .ok_or_else(|| anyhow!("Delete object is missing"))?;
+
+    if config.is_own_url(&object_uri) {
+        bail!("{actor_uri} tried to delete our object {object_uri}");
+    }
+
+    let note = match Note::find_by_uri(&object_uri, db).await {
+        Ok(note) => note,
+        Err(e) if e.is_not_found() => {
+            // This is also what we get for deleted accounts, which we don't handle yet
+            log::debug!("Ignoring delete of unknown object {object_uri}");
+            return Ok(());
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    let actor = helpers::find_or_fetch_account_by_uri(&actor_uri, &config, db, ch).await?;
+
+    if note.from.as_deref() != Some(actor.key().as_str()) {
+        bail!("{actor_uri} tried to delete a note that isn't theirs: {object_uri}");
+    }
+
+    Action::DeleteNote(note.key().to_owned()).send(ch).await?;
+
+    Ok(())
+}
+
+async fn process_create(
+    activity: activity::Create,
+    config: &Config,
+    ch: &Channel,
+    db: &DatabaseConnection,
+) -> Result<()> {
+    log::debug!("Process remote create {activity:?}");
+
+    let actor_uri: Url = activity.create_props.get_actor_xsd_any_uri()
+        .ok_or_else(|| anyhow!("Create actor is not URI"))?
+        .as_url().clone();
+
+    let object = activity.create_props.get_object_base_box()
+        .ok_or_else(|| anyhow!("Create object is not embedded"))?
+        .clone();
+
+    match object.kind() {
+        Some("Note") => {
+            let note: object::Note = object.into_concrete()?;
+
+            let attributed_to = note.object_props.get_attributed_to_xsd_any_uri()
+                .map(|uri| uri.as_url().clone());
+
+            if attributed_to.as_ref() != Some(&actor_uri) {
+                bail!("Create actor {actor_uri} is not the author of the note");
+            }
+
+            let json = serde_json::to_value(&note)?;
+
+            let mut note = Note::try_from(note)?;
+
+            let author = helpers::find_or_fetch_account_by_uri(
+                &actor_uri, &config, db, ch).await?;
+
+            if author.is_local() {
+                bail!("Remote server tried to create a note for local account {actor_uri}");
+            }
+
+            note.from = Some(author.key().to_owned());
+
+            // Remote notes without a published date, or with one in the future, are sorted as if
+            // they were published now
+            let now = Utc::now();
+            note.created_at = Some(note.created_at.map_or(now, |created_at| created_at.min(now)));
+
+            // Link the reply to its parent, if we have it and the author can see it
+            if let Some(parent_uri) = note.remote.as_ref().and_then(|r| r.in_reply_to.clone()) {
+                match helpers::find_note_by_uri(&parent_uri, config, db).await {
+                    Ok(parent) if Note::can_view(&parent, Some(&author), db).await? =>
+                        note.in_reply_to = Some(parent.key().to_owned()),
+                    Ok(_) => log::debug!("Parent {parent_uri} is hidden from the note's author"),
+                    Err(e) => log::debug!("Parent {parent_uri} of remote note not found: {e}"),
+                }
+            }
+
+            // Keep only the recipients that we know about
+            note.to.extend(known_recipients(&get_ids(&json, "to"), &author, config, db).await?);
+            note.cc.extend(known_recipients(&get_ids(&json, "cc"), &author, config, db).await?);
+
+            // Mentioned accounts that we know about are addressed too
+            for (href, name) in get_mention_tags(&json) {
+                if let Some(account) = known_account(&href, config, db).await? {
+                    note.add_mention(&account, name.unwrap_or_else(|| href.to_string()));
+                }
+            }
+
+            Action::ReceiveNote(note).send(ch).await?;
+        },
+        kind => bail!("Unprocessable Create object type: {kind:?}")
+    }
+
+    Ok(())
+}
+
+/// Find the accounts we already have for a list of recipient URIs. The author's followers
+/// collection is kept too. Unknown accounts and other collections are skipped.
+async fn known_recipients(
+    uris: &[Url],
+    author: &Document<Account>,
+    config: &Config,
+    db: &DatabaseConnection,
+) -> Result<Vec<Recipient>> {
+    let mut recipients = vec![];
+
+    let author_followers = author.remote.as_ref().and_then(|r| r.followers.as_ref());
+
+    for uri in uris {
+        if Some(uri) == author_followers {
+            recipients.push(Recipient::Followers(author.key().to_owned()));
+            continue;
+        }
+
+        if let Some(account) = known_account(uri, config, db).await? {
+            recipients.push(Recipient::Account(account.key().to_owned()));
+        }
+    }
+
+    Ok(recipients)
+}
+
+/// Find the account that we already have for a URI, which may be local or remote.
+async fn known_account(
+    uri: &Url,
+    config: &Config,
+    db: &DatabaseConnection,
+) -> Result<Option<Document<Account>>> {
+    if config.is_own_url(uri) {
+        let username = uri.path().strip_prefix("/users/");
+
+        Ok(match username {
+            Some(username) if !username.contains('/') =>
+                Account::find_by_username(username, None, db).await.ok(),
+            _ => None
+        })
+    } else {
+        match Account::find_by_uri(uri, db).await {
+            Ok(account) => Ok(Some(account)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
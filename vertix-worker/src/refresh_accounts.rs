@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use lapin::Channel;
+use vertix_app_common::Config;
+use vertix_comm::messages::Action;
+use vertix_model::{AragogConnectionManager, Account};
+use anyhow::Result;
+
+/// Periodically look for remote accounts that haven't been fetched in a while, and fetch them
+/// again. Only a few accounts on each domain are fetched each time, so that a server we know many
+/// accounts on isn't flooded with requests.
+pub async fn run(
+    ch: &Channel,
+    config: Arc<Config>,
+    pool: bb8::Pool<AragogConnectionManager>
+) -> Result<()> {
+    log::debug!("Refreshing stale accounts every {:?}", config.account_refresh_interval);
+
+    let mut interval = actix_rt::time::interval(config.account_refresh_interval);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = refresh(&*config, ch, &pool).await {
+            log::warn!("Error while refreshing stale accounts: {err}");
+        }
+    }
+}
+
+async fn refresh(
+    config: &Config,
+    ch: &Channel,
+    pool: &bb8::Pool<AragogConnectionManager>
+) -> Result<()> {
+    let db = pool.get().await?;
+
+    let accounts = Account::find_stale_remote(
+        config.account_max_age, config.account_refresh_per_domain, &*db).await?;
+
+    log::debug!("Refreshing {} stale accounts", accounts.len());
+
+    if accounts.is_empty() {
+        return Ok(());
+    }
+
+    // So that accounts that can't be fetched don't keep being picked first
+    Action::RecordRefreshAttempts(accounts.iter().map(|account| account.key().clone()).collect())
+        .send(ch).await?;
+
+    for account in accounts {
+        if let Some(ref remote) = account.remote {
+            Action::FetchAccount(remote.uri.clone()).send(ch).await?;
+        }
+    }
+
+    Ok(())
+}
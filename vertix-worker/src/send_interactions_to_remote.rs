@@ -1,20 +1,28 @@
+use std::collections::BTreeSet;
 use std::sync::Arc;
 
-use activitystreams::activity;
+use activitystreams::activity::{self, ActivityBox};
 use activitystreams::activity::properties::ActorAndObjectProperties;
 
+use aragog::DatabaseAccess;
 use lapin::Channel;
 use anyhow::Result;
-use aragog::Record;
 use anyhow::anyhow;
+use chrono::Utc;
+use url::Url;
 
 use vertix_comm::{SendMessage, ReceiveMessage};
 use vertix_comm::messages::{Interaction, DeliverActivity};
-use vertix_model::{AragogConnectionManager, Account};
-use vertix_model::activitystreams::{UrlFor, ToObject};
+use vertix_model::{AragogConnectionManager, Account, Document, Note, Recipient};
+use vertix_model::activitystreams::{
+    UrlFor,
+    ToObject,
+    make_actor_and_object_activity,
+    make_tombstone,
+};
 use vertix_app_common::{Urls, Config};
 
-use futures::stream::StreamExt;
+use futures::stream::{StreamExt, FuturesOrdered, TryStreamExt};
 
 pub async fn listen(
     ch: &Channel,
@@ -58,10 +66,109 @@ async fn process_interaction(
     let urls = Urls::new(&config.base_url, &*db);
 
     match interaction {
+        Interaction::UpdateAccount(account) if account.is_local() => {
+            let inboxes = Account::get_follower_inboxes(account, &*db).await?;
+            log::debug!("Send Update/Person to {} inboxes: {account:?}", inboxes.len());
+
+            let account_url = urls.url_for_account(account.key()).await?;
+            let person = account.to_object::<_, anyhow::Error>(&urls).await?;
+
+            // Each update needs a distinct id
+            let updated_at = account.updated_at.unwrap_or_else(Utc::now);
+            let mut update_url = account_url.clone();
+            update_url.set_fragment(Some(&format!("updates/{}", updated_at.timestamp_millis())));
+
+            let mut update = activity::Update::new();
+            update.object_props.set_context_xsd_any_uri(activitystreams::context())?;
+            update.object_props.set_id(update_url)?;
+            update.object_props.set_to_xsd_any_uri(activitystreams::public())?;
+            update.object_props.set_cc_xsd_any_uri(
+                urls.url_for_account_followers(account.key()).await?)?;
+            update.update_props.set_actor_xsd_any_uri(account_url)?;
+            update.update_props.set_object_base_box(person)?;
+
+            deliver_to_inboxes(account.key(), inboxes, update.try_into()?, ch).await?;
+        },
+
         Interaction::Note(note) if note.from.is_some() => {
-            let from = Account::find(note.from.as_ref().unwrap(), &*db).await?;
+            let from = urls.account_cache.get(note.from.as_ref().unwrap(), &*db).await?;
+            if from.is_local() {
+                let inboxes = note_inboxes(note, &from, &urls, &*db).await?;
+                log::debug!("Send Create/Note to {} inboxes: {note:?}", inboxes.len());
+
+                let (to_urls, cc_urls) = futures::try_join!(
+                    recipient_urls(&note.to, &urls),
+                    recipient_urls(&note.cc, &urls),
+                )?;
+
+                let note_object = note.to_object::<_, anyhow::Error>(&urls).await?;
+                let mut create = make_actor_and_object_activity::<activity::Create, _>(note_object)?;
+
+                let mut create_url = urls.url_for_note(note.key()).await?;
+                create_url.set_fragment(Some("create"));
+
+                create.object_props.set_context_xsd_any_uri(activitystreams::context())?;
+                create.object_props.set_id(create_url)?;
+                create.object_props.set_many_to_xsd_any_uris(to_urls)?;
+                create.object_props.set_many_cc_xsd_any_uris(cc_urls)?;
+
+                deliver_to_inboxes(from.key(), inboxes, create.try_into()?, ch).await?;
+            }
+        },
+
+        Interaction::EditNote(note) if note.from.is_some() => {
+            let from = urls.account_cache.get(note.from.as_ref().unwrap(), &*db).await?;
             if from.is_local() {
-                log::warn!("TODO: Send note to inboxes of followers, recipients: {note:?}");
+                let inboxes = note_inboxes(note, &from, &urls, &*db).await?;
+                log::debug!("Send Update/Note to {} inboxes: {note:?}", inboxes.len());
+
+                let (to_urls, cc_urls) = futures::try_join!(
+                    recipient_urls(&note.to, &urls),
+                    recipient_urls(&note.cc, &urls),
+                )?;
+
+                let note_object = note.to_object::<_, anyhow::Error>(&urls).await?;
+                let mut update = make_actor_and_object_activity::<activity::Update, _>(note_object)?;
+
+                // Each edit needs a distinct id
+                let mut update_url = urls.url_for_note(note.key()).await?;
+                update_url.set_fragment(Some(&format!("updates/{}", note.revisions.len())));
+
+                update.object_props.set_context_xsd_any_uri(activitystreams::context())?;
+                update.object_props.set_id(update_url)?;
+                update.object_props.set_many_to_xsd_any_uris(to_urls)?;
+                update.object_props.set_many_cc_xsd_any_uris(cc_urls)?;
+
+                deliver_to_inboxes(from.key(), inboxes, update.try_into()?, ch).await?;
+            }
+        },
+
+        Interaction::DeleteNote(note) if note.from.is_some() => {
+            let from = urls.account_cache.get(note.from.as_ref().unwrap(), &*db).await?;
+            if from.is_local() {
+                let inboxes = note_inboxes(note, &from, &urls, &*db).await?;
+                log::debug!("Send Delete/Note to {} inboxes: {note:?}", inboxes.len());
+
+                let (to_urls, cc_urls) = futures::try_join!(
+                    recipient_urls(&note.to, &urls),
+                    recipient_urls(&note.cc, &urls),
+                )?;
+
+                let note_url = urls.url_for_note(note.key()).await?;
+                let tombstone = make_tombstone(note_url.clone(), "Note", Some(Utc::now()))?;
+
+                let mut delete_url = note_url;
+                delete_url.set_fragment(Some("delete"));
+
+                let mut delete = activity::Delete::new();
+                delete.object_props.set_context_xsd_any_uri(activitystreams::context())?;
+                delete.object_props.set_id(delete_url)?;
+                delete.object_props.set_many_to_xsd_any_uris(to_urls)?;
+                delete.object_props.set_many_cc_xsd_any_uris(cc_urls)?;
+                delete.delete_props.set_actor_xsd_any_uri(urls.url_for_account(from.key()).await?)?;
+                delete.delete_props.set_object_base_box(tombstone)?;
+
+                deliver_to_inboxes(from.key(), inboxes, delete.try_into()?, ch).await?;
             }
         },
 
@@ -71,7 +178,11 @@ async fn process_interaction(
                 log::debug!("Send Follow to remote {follow:?}");
                 let follow_activity = follow.to_object::<_, anyhow::Error>(&urls).await?;
                 let inbox = urls.url_for_account_inbox(&follow.key_to()).await?;
-                DeliverActivity { inbox, activity: follow_activity.try_into()? }.send(&ch).await?;
+                DeliverActivity {
+                    from_account: follow.key_from().to_owned(),
+                    inbox,
+                    activity: follow_activity.try_into()?,
+                }.send(&ch).await?;
             }
         },
 
@@ -88,7 +199,101 @@ async fn process_interaction(
                 } else {
                     make_follow_response::<activity::Reject>(follow_activity)?.try_into()?
                 };
-                DeliverActivity { inbox, activity }.send(&ch).await?;
+                DeliverActivity {
+                    from_account: follow.key_to().to_owned(),
+                    inbox,
+                    activity,
+                }.send(&ch).await?;
+            }
+        },
+
+        Interaction::RemoveFollow(follow) if follow.to_remote && !follow.from_remote => {
+            log::debug!("Send Undo/Follow to remote {follow:?}");
+            let follow_activity = follow.to_object::<_, anyhow::Error>(&urls).await?;
+            let inbox = urls.url_for_account_inbox(&follow.key_to()).await?;
+
+            let from_url = urls.url_for_account(follow.key_from()).await?;
+            let mut undo_url = from_url.clone();
+            undo_url.set_fragment(Some(&format!("follows/{}/undo", follow.key())));
+
+            let undo = make_undo(undo_url, from_url, follow_activity)?;
+
+            DeliverActivity {
+                from_account: follow.key_from().to_owned(),
+                inbox,
+                activity: undo.try_into()?,
+            }.send(&ch).await?;
+        },
+
+        Interaction::Like { like, note } if note.from.is_some() => {
+            let from = urls.account_cache.get(like.key_from(), &*db).await?;
+            let author = urls.account_cache.get(note.from.as_ref().unwrap(), &*db).await?;
+
+            if from.is_local() && author.is_remote() {
+                log::debug!("Send Like to remote {like:?}");
+                let like_activity = like.to_object::<_, anyhow::Error>(&urls).await?;
+                let inbox = urls.url_for_account_inbox(author.key()).await?;
+                DeliverActivity {
+                    from_account: from.key().to_owned(),
+                    inbox,
+                    activity: like_activity.try_into()?,
+                }.send(&ch).await?;
+            }
+        },
+
+        Interaction::Unlike { like, note } if note.from.is_some() => {
+            let from = urls.account_cache.get(like.key_from(), &*db).await?;
+            let author = urls.account_cache.get(note.from.as_ref().unwrap(), &*db).await?;
+
+            if from.is_local() && author.is_remote() {
+                log::debug!("Send Undo/Like to remote {like:?}");
+                let like_activity = like.to_object::<_, anyhow::Error>(&urls).await?;
+                let inbox = urls.url_for_account_inbox(author.key()).await?;
+
+                let from_url = urls.url_for_account(from.key()).await?;
+                let mut undo_url = from_url.clone();
+                undo_url.set_fragment(Some(&format!("likes/{}/undo", like.key())));
+
+                let undo = make_undo(undo_url, from_url, like_activity)?;
+
+                DeliverActivity {
+                    from_account: from.key().to_owned(),
+                    inbox,
+                    activity: undo.try_into()?,
+                }.send(&ch).await?;
+            }
+        },
+
+        Interaction::Share { share, note } => {
+            let from = urls.account_cache.get(share.key_from(), &*db).await?;
+
+            if from.is_local() {
+                let inboxes = share_inboxes(&from, note, &urls, &*db).await?;
+                log::debug!("Send Announce to {} inboxes: {share:?}", inboxes.len());
+
+                let announce = share.to_object::<_, anyhow::Error>(&urls).await?;
+
+                deliver_to_inboxes(from.key(), inboxes, announce.try_into()?, ch).await?;
+            }
+        },
+
+        Interaction::Unshare { share, note } => {
+            let from = urls.account_cache.get(share.key_from(), &*db).await?;
+
+            if from.is_local() {
+                let inboxes = share_inboxes(&from, note, &urls, &*db).await?;
+                log::debug!("Send Undo/Announce to {} inboxes: {share:?}", inboxes.len());
+
+                let announce = share.to_object::<_, anyhow::Error>(&urls).await?;
+
+                let from_url = urls.url_for_account(from.key()).await?;
+                let mut undo_url = from_url.clone();
+                undo_url.set_fragment(Some(&format!("shares/{}/undo", share.key())));
+
+                let mut undo = make_undo(undo_url, from_url, announce)?;
+                undo.object_props.set_to_xsd_any_uri(activitystreams::public())?;
+
+                deliver_to_inboxes(from.key(), inboxes, undo.try_into()?, ch).await?;
             }
         },
         _ => ()
@@ -96,6 +301,133 @@ async fn process_interaction(
     Ok(())
 }
 
+/// Get the urls of a list of recipients.
+async fn recipient_urls<'a, D>(recipients: &[Recipient], urls: &Urls<'a, D>) -> Result<Vec<Url>>
+where
+    D: DatabaseAccess,
+{
+    Ok(FuturesOrdered::from_iter(recipients.iter().map(|r| r.url_for(urls)))
+        .try_collect().await?)
+}
+
+/// Find the remote inboxes that a note from a local author should be delivered to.
+///
+/// Public notes and notes addressed to the author's followers go to all of the author's
+/// followers. Remote accounts among the recipients are added too, and public replies also go to
+/// the author of the note being replied to. Shared inboxes are used where available, so each
+/// server only gets one copy.
+async fn note_inboxes<'a, D>(
+    note: &Document<Note>,
+    author: &Document<Account>,
+    urls: &Urls<'a, D>,
+    db: &D,
+) -> Result<BTreeSet<Url>>
+where
+    D: DatabaseAccess,
+{
+    let mut inboxes = BTreeSet::new();
+
+    let recipients = || note.to.iter().chain(&note.cc).chain(&note.bto).chain(&note.bcc);
+
+    let public = recipients().any(|r| matches!(r, Recipient::Public));
+
+    let to_followers = recipients().any(|r| match r {
+        Recipient::Public => true,
+        Recipient::Followers(key) => key == author.key(),
+        Recipient::Account(_) => false,
+    });
+
+    if to_followers {
+        inboxes.extend(Account::get_follower_inboxes(author, db).await?);
+    }
+
+    for recipient in recipients() {
+        if let Recipient::Account(key) = recipient {
+            let account = urls.account_cache.get(key, db).await?;
+
+            if let Some(inbox) = account.remote.as_ref().and_then(|r| r.delivery_inbox()) {
+                inboxes.insert(inbox.clone());
+            }
+        }
+    }
+
+    // Anyone can see a public reply, so the author of the note being replied to gets it even if
+    // they don't follow. Otherwise they only get it if it's addressed to them, as above.
+    if public {
+        if let Some(ref parent) = note.in_reply_to {
+            let parent = urls.note_cache.get(parent, db).await?;
+
+            if let Some(ref parent_author) = parent.from {
+                let parent_author = urls.account_cache.get(parent_author, db).await?;
+
+                let inbox = parent_author.remote.as_ref().and_then(|r| r.delivery_inbox());
+
+                if let Some(inbox) = inbox {
+                    inboxes.insert(inbox.clone());
+                }
+            }
+        }
+    }
+
+    Ok(inboxes)
+}
+
+/// Find the remote inboxes that a share from a local account should be delivered to: the sharer's
+/// followers, and the author of the note.
+async fn share_inboxes<'a, D>(
+    sharer: &Document<Account>,
+    note: &Document<Note>,
+    urls: &Urls<'a, D>,
+    db: &D,
+) -> Result<BTreeSet<Url>>
+where
+    D: DatabaseAccess,
+{
+    let mut inboxes: BTreeSet<Url> = Account::get_follower_inboxes(sharer, db).await?
+        .into_iter().collect();
+
+    if let Some(ref author) = note.from {
+        let author = urls.account_cache.get(author, db).await?;
+
+        if let Some(inbox) = author.remote.as_ref().and_then(|r| r.delivery_inbox()) {
+            inboxes.insert(inbox.clone());
+        }
+    }
+
+    Ok(inboxes)
+}
+
+/// Queue delivery of an activity from a local account to each of the inboxes.
+async fn deliver_to_inboxes(
+    from_account: &str,
+    inboxes: impl IntoIterator<Item = Url>,
+    activity: ActivityBox,
+    ch: &Channel,
+) -> Result<()> {
+    for inbox in inboxes {
+        DeliverActivity {
+            from_account: from_account.to_owned(),
+            inbox,
+            activity: activity.clone(),
+        }.send(ch).await?;
+    }
+
+    Ok(())
+}
+
+/// Make an Undo of an activity previously sent by `actor_url`.
+fn make_undo<O>(id: Url, actor_url: Url, object: O) -> Result<activity::Undo>
+where
+    O: TryInto<activitystreams::BaseBox, Error = std::io::Error>,
+{
+    let mut undo = activity::Undo::new();
+    undo.object_props.set_context_xsd_any_uri(activitystreams::context())?;
+    undo.object_props.set_id(id)?;
+    undo.undo_props.set_actor_xsd_any_uri(actor_url)?;
+    undo.undo_props.set_object_base_box(object)?;
+    Ok(undo)
+}
+
 fn make_follow_response<A>(follow: activity::Follow) -> Result<A>
     where A: activity::Activity + Default + AsMut<ActorAndObjectProperties>,
 {